@@ -0,0 +1,253 @@
+use clap::{App, AppSettings, Arg, SubCommand};
+
+pub fn build_app() -> App<'static, 'static> {
+    App::new("countcode")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about("Counts lines of code, comments and blanks very quickly.")
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .setting(AppSettings::UnifiedHelpMessage)
+        .after_help("Paths that start with '-' can be passed after '--', e.g. `countcode -- -v`.")
+        .arg(
+            Arg::with_name("paths")
+                .value_name("PATHS")
+                .help("Files or directories to scan [default: the current directory]")
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FORMAT")
+                .help("Output format")
+                .possible_values(&["table", "json", "csv", "tsv"])
+                .default_value("table"),
+        )
+        .arg(
+            Arg::with_name("files")
+                .short("f")
+                .long("files")
+                .help("List every counted file instead of totals per language"),
+        )
+        .arg(
+            Arg::with_name("dirs")
+                .short("d")
+                .long("dirs")
+                .help("Show totals per directory instead of per language")
+                .conflicts_with_all(&["files", "tree"]),
+        )
+        .arg(
+            Arg::with_name("tree")
+                .short("t")
+                .long("tree")
+                .help("Show totals for every directory as a tree")
+                .conflicts_with("files"),
+        )
+        .arg(
+            Arg::with_name("depth")
+                .long("depth")
+                .value_name("N")
+                .help(
+                    "With --dirs or --tree, how many directory levels to show \
+                     [default: 1 with --dirs, unlimited with --tree]",
+                )
+                .validator(|n| match n.parse::<usize>() {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err("must be a number".to_owned()),
+                }),
+        )
+        .arg(
+            Arg::with_name("lang")
+                .short("l")
+                .long("lang")
+                .value_name("LANGUAGE")
+                .help("With --files, only list files in LANGUAGE (repeatable)")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("files"),
+        )
+        .arg(
+            Arg::with_name("min-lines")
+                .long("min-lines")
+                .value_name("N")
+                .help("With --files, only list files with at least N lines")
+                .requires("files")
+                .validator(|n| match n.parse::<u64>() {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err("must be a number".to_owned()),
+                }),
+        )
+        .arg(
+            Arg::with_name("no-total")
+                .long("no-total")
+                .help("Leave out the TOTAL row in CSV and TSV output"),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .short("s")
+                .long("sort")
+                .value_name("KEY")
+                .help("Column to sort languages or files by")
+                .possible_values(&["name", "files", "lines", "code", "comments", "blanks"])
+                .default_value("lines"),
+        )
+        .arg(
+            Arg::with_name("order")
+                .long("order")
+                .value_name("ORDER")
+                .help(
+                    "Sort in ascending or descending order \
+                     [default: asc for name, desc otherwise]",
+                )
+                .possible_values(&["asc", "desc"]),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .short("c")
+                .long("columns")
+                .value_name("COLUMNS")
+                .help("Comma-separated list of columns to show, in order")
+                .possible_values(&[
+                    "files",
+                    "lines",
+                    "code",
+                    "comments",
+                    "blanks",
+                    "mixed",
+                    "comment-ratio",
+                    "code-share",
+                ])
+                .use_delimiter(true)
+                .multiple(true)
+                .require_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .short("e")
+                .long("exclude")
+                .value_name("GLOB")
+                .help("Skip files and directories matching GLOB (repeatable)")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("include")
+                .short("i")
+                .long("include")
+                .value_name("GLOB")
+                .help("Only count files matching GLOB (repeatable)")
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("no-ignore")
+                .long("no-ignore")
+                .help("Don't respect .gitignore, .ignore or .countcodeignore files"),
+        )
+        .arg(
+            Arg::with_name("no-ignore-vcs")
+                .long("no-ignore-vcs")
+                .help("Don't respect .gitignore files and other git excludes"),
+        )
+        .arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .value_name("N")
+                .help("Don't descend more than N directories below each path")
+                .validator(|n| match n.parse::<usize>() {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err("must be a number".to_owned()),
+                }),
+        )
+        .arg(
+            Arg::with_name("hidden")
+                .long("hidden")
+                .help("Count hidden files and directories"),
+        )
+        .arg(
+            Arg::with_name("no-hidden")
+                .long("no-hidden")
+                .help("Skip hidden files and directories, even if the config file counts them")
+                .conflicts_with("hidden"),
+        )
+        .arg(
+            Arg::with_name("follow")
+                .short("L")
+                .long("follow")
+                .help("Follow symbolic links"),
+        )
+        .arg(
+            Arg::with_name("no-follow")
+                .long("no-follow")
+                .help("Don't follow symbolic links, even if the config file does")
+                .conflicts_with("follow"),
+        )
+        .args(&config_args(
+            "Read settings from FILE [default: the nearest .countcode.toml above the first path]",
+        ))
+        .arg(
+            Arg::with_name("threads")
+                .short("j")
+                .long("threads")
+                .value_name("N")
+                .help("Number of threads to count with [default: number of CPUs]")
+                .validator(|n| match n.parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(()),
+                    _ => Err("must be a positive number".to_owned()),
+                }),
+        )
+        .arg(
+            Arg::with_name("mixed")
+                .long("mixed")
+                .value_name("POLICY")
+                .help("How to count lines with both code and a comment")
+                .possible_values(&["code", "comment", "separate"])
+                .default_value("code"),
+        )
+        .arg(
+            Arg::with_name("errors")
+                .short("v")
+                .long("errors")
+                .visible_alias("verbose")
+                .help("List the files that could not be counted"),
+        )
+        .arg(
+            Arg::with_name("fail-on-error")
+                .long("fail-on-error")
+                .help("Exit with status 1 if any file could not be counted"),
+        )
+        .arg(
+            Arg::with_name("report-non-utf8")
+                .long("report-non-utf8")
+                .help("List the files that contain non-UTF-8 content"),
+        )
+        .subcommand(
+            SubCommand::with_name("languages")
+                .about("Lists the supported languages and their file extensions")
+                .args(&config_args(
+                    "Read settings from FILE [default: the nearest .countcode.toml above the \
+                     current directory]",
+                )),
+        )
+}
+
+// Where settings and language definitions come from, for counting as well as
+// for listing languages.
+fn config_args(config_help: &'static str) -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("config")
+            .long("config")
+            .value_name("FILE")
+            .help(config_help),
+        Arg::with_name("no-config")
+            .long("no-config")
+            .help("Don't read any configuration file")
+            .conflicts_with("config"),
+        Arg::with_name("languages")
+            .long("languages")
+            .value_name("FILE")
+            .help("Read more language definitions from FILE")
+            .multiple(true)
+            .number_of_values(1),
+    ]
+}
@@ -0,0 +1,122 @@
+use counter::MixedLines;
+use globset;
+use lang::LanguageDef;
+use scanner::{SortKey, SortOrder};
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use toml;
+
+pub const CONFIG_FILENAME: &str = ".countcode.toml";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub hidden: Option<bool>,
+    pub follow: Option<bool>,
+    pub max_depth: Option<usize>,
+    pub mixed: Option<MixedLines>,
+    pub sort: Option<SortKey>,
+    pub order: Option<SortOrder>,
+    pub output: Option<String>,
+    pub columns: Option<Vec<String>>,
+    // Maps globs such as `*.inc` to the name of the language to count
+    // matching files as, ahead of the extension table.
+    pub languages: BTreeMap<String, String>,
+    // Definitions of languages countcode doesn't know about, or replacements
+    // for ones it does, in the same form as the bundled definitions.
+    pub language: Vec<LanguageDef>,
+    // Files of such definitions, relative to the config file.
+    pub language_files: Vec<PathBuf>,
+    pub thresholds: Thresholds,
+    // The directory holding the config file, which globs are relative to.
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Thresholds {
+    pub max_file_lines: Option<u64>,
+    pub max_total_code: Option<u64>,
+    pub min_comment_ratio: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    UnknownLanguage(String),
+    InvalidGlob(String, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            Error::Parse(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            Error::UnknownLanguage(ref name) => write!(f, "unknown language '{}'", name),
+            Error::InvalidGlob(ref glob, ref err) => {
+                write!(f, "invalid pattern '{}': {}", glob, err)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<globset::Error> for Error {
+    fn from(err: globset::Error) -> Self {
+        Error::InvalidGlob(err.glob().unwrap_or_default().to_owned(), err.to_string())
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let contents = fs::read_to_string(path).map_err(|e| Error::Io(path.to_path_buf(), e))?;
+        let mut config: Config =
+            toml::from_str(&contents).map_err(|e| Error::Parse(path.to_path_buf(), e))?;
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        for file in &mut config.language_files {
+            *file = dir.join(&*file);
+        }
+        config.root = Some(
+            dir.canonicalize()
+                .map_err(|e| Error::Io(dir.to_path_buf(), e))?,
+        );
+        Ok(config)
+    }
+
+    // Looks for a config file in `path` and each of its ancestors, returning
+    // the first one found along with where it was found.
+    pub fn discover(path: &Path) -> Result<Option<(PathBuf, Config)>, Error> {
+        // A path that doesn't exist has no config; scanning it will say so.
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(_) => return Ok(None),
+        };
+        let start = if path.is_dir() {
+            path.as_path()
+        } else {
+            path.parent().unwrap_or(&path)
+        };
+
+        for dir in start.ancestors() {
+            let candidate = dir.join(CONFIG_FILENAME);
+            if candidate.is_file() {
+                return Config::load(&candidate).map(|config| Some((candidate, config)));
+            }
+        }
+
+        Ok(None)
+    }
+}
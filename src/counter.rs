@@ -1,17 +1,20 @@
 use lang::{CommentInfo, Lang};
 use memmap::Mmap;
+use std::cmp::Reverse;
 use std::fs::File;
+use std::io::{self, Read};
 use std::ops::AddAssign;
 use std::path::Path;
 use std::str;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Default, Serialize)]
 pub struct Stats {
     pub files: u64,
     pub lines: u64,
     pub code: u64,
     pub comments: u64,
     pub blanks: u64,
+    pub mixed: u64,
 }
 
 impl AddAssign for Stats {
@@ -21,6 +24,7 @@ impl AddAssign for Stats {
         self.comments += rhs.comments;
         self.code += rhs.code;
         self.blanks += rhs.blanks;
+        self.mixed += rhs.mixed;
     }
 }
 
@@ -31,35 +35,30 @@ impl<'a> AddAssign<&'a Stats> for Stats {
         self.comments += rhs.comments;
         self.code += rhs.code;
         self.blanks += rhs.blanks;
+        self.mixed += rhs.mixed;
     }
 }
 
-#[derive(Clone)]
+// How to count lines that contain both code and a comment.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MixedLines {
+    Code,
+    Comment,
+    Separate,
+}
+
+#[derive(Clone, Debug)]
 pub struct Sloc {
     pub lang: Lang,
     pub stats: Stats,
 }
 
-pub struct SlocStr {
-    pub lang: String,
-    pub files: String,
-    pub lines: String,
-    pub code: String,
-    pub comments: String,
-    pub blanks: String,
-}
-
 impl Sloc {
     pub fn new(lang: Lang) -> Self {
         Self {
             lang,
-            stats: Stats {
-                files: 0,
-                lines: 0,
-                code: 0,
-                comments: 0,
-                blanks: 0,
-            },
+            stats: Stats::default(),
         }
     }
 }
@@ -77,7 +76,7 @@ impl<'a> LineReader<'a> {
         }
     }
 
-    fn read_line(&mut self) -> Option<&'a str> {
+    fn read_line(&mut self) -> Option<&'a [u8]> {
         let starting_index = self.next_index;
         let mut end_index = self.next_index;
 
@@ -90,12 +89,12 @@ impl<'a> LineReader<'a> {
                         self.next_index += 1;
                     }
 
-                    return str::from_utf8(&self.mmap[starting_index..end_index]).ok();
+                    return Some(&self.mmap[starting_index..end_index]);
                 }
 
                 b'\n' => {
                     self.next_index = end_index + 1;
-                    return str::from_utf8(&self.mmap[starting_index..end_index]).ok();
+                    return Some(&self.mmap[starting_index..end_index]);
                 }
 
                 _ => {
@@ -104,104 +103,1040 @@ impl<'a> LineReader<'a> {
             }
         }
 
+        if starting_index < length {
+            self.next_index = length;
+            return Some(&self.mmap[starting_index..length]);
+        }
+
         None
     }
 }
 
-pub struct Counter<'a> {
-    path: &'a Path,
-    lang: Lang,
-    comment_info: CommentInfo,
+#[derive(Clone, Copy)]
+enum LexState<'c> {
+    Code,
+    Comment {
+        end: &'c str,
+        nest: Option<&'c str>,
+        depth: usize,
+    },
+    // The closing delimiter and the escape character, if any.
+    Quote(&'c str, Option<u8>),
 }
 
-impl<'a> Counter<'a> {
-    pub fn new(path: &'a Path, lang: Lang, comment_info: CommentInfo) -> Self {
+#[derive(Debug, PartialEq)]
+enum LineKind {
+    Blank,
+    Code,
+    Comment,
+    Mixed,
+}
+
+struct Lexer<'c> {
+    comment_info: &'c CommentInfo,
+    state: LexState<'c>,
+    // Words that end the here-documents opened on earlier lines, in order.
+    heredoc_ends: Vec<Vec<u8>>,
+    // The delimiters of the own-line comment being read and how deeply it is
+    // nested.
+    own_line_comment: Option<(&'c str, &'c str, usize)>,
+}
+
+impl<'c> Lexer<'c> {
+    fn new(comment_info: &'c CommentInfo) -> Self {
         Self {
-            path,
-            lang,
             comment_info,
+            state: LexState::Code,
+            heredoc_ends: Vec::new(),
+            own_line_comment: None,
         }
     }
 
-    pub fn count(&mut self) -> Option<Sloc> {
-        match File::open(&self.path) {
-            Ok(f) => {
-                let mmap = match unsafe { Mmap::map(&f) } {
-                    Ok(mmap) => mmap,
-                    Err(_) => {
-                        return None;
+    // Whether `line`, untrimmed, is a comment because of its first column.
+    fn is_column_comment(&self, line: &[u8]) -> bool {
+        match self.state {
+            LexState::Code if self.heredoc_ends.is_empty() && self.own_line_comment.is_none() => {
+                self.comment_info
+                    .column_comments
+                    .iter()
+                    .any(|marker| line.starts_with(marker.as_bytes()))
+            }
+            _ => false,
+        }
+    }
+
+    fn classify(&mut self, line: &[u8]) -> LineKind {
+        if !self.heredoc_ends.is_empty() {
+            if line == &self.heredoc_ends[0][..] {
+                self.heredoc_ends.remove(0);
+            }
+            return LineKind::Code;
+        }
+
+        if let Some((start, end, depth)) = self.own_line_comment {
+            self.own_line_comment = if line == end.as_bytes() {
+                depth.checked_sub(1).map(|depth| (start, end, depth))
+            } else if line == start.as_bytes() {
+                Some((start, end, depth + 1))
+            } else {
+                Some((start, end, depth))
+            };
+            return LineKind::Comment;
+        }
+
+        if let LexState::Code = self.state {
+            if let Some((start, end)) = self
+                .comment_info
+                .own_line_comments
+                .iter()
+                .find(|(start, _)| line == start.as_bytes())
+            {
+                self.own_line_comment = Some((start, end, 0));
+                return LineKind::Comment;
+            }
+        }
+
+        let mut has_code = false;
+        let mut has_comment = false;
+        // Parentheses left open in a `((` or `$((` arithmetic expression,
+        // where `<<` is a shift rather than a here-document.
+        let mut arith_parens = 0;
+
+        let mut i = 0;
+        while i < line.len() {
+            let rest = &line[i..];
+            match self.state {
+                LexState::Comment { end, nest, depth } => {
+                    has_comment = true;
+                    if let Some(start) = nest.filter(|start| rest.starts_with(start.as_bytes())) {
+                        self.state = LexState::Comment {
+                            end,
+                            nest,
+                            depth: depth + 1,
+                        };
+                        i += start.len();
+                    } else if rest.starts_with(end.as_bytes()) {
+                        self.state = if depth == 0 {
+                            LexState::Code
+                        } else {
+                            LexState::Comment {
+                                end,
+                                nest,
+                                depth: depth - 1,
+                            }
+                        };
+                        i += end.len();
+                    } else {
+                        i += 1;
                     }
-                };
+                }
 
-                let mut line_reader = LineReader::new(&mmap);
-                let mut sloc = Sloc::new(self.lang.clone());
-                sloc.stats.files = 1;
+                LexState::Quote(end, escape) => {
+                    has_code = true;
+                    if escape == Some(rest[0]) {
+                        i += 2;
+                    } else if rest.starts_with(end.as_bytes()) {
+                        self.state = LexState::Code;
+                        i += end.len();
+                    } else {
+                        i += 1;
+                    }
+                }
 
-                let mut multi_line_comment = false;
-                let mut multi_line_comment_index = 0;
+                LexState::Code => {
+                    if rest[0].is_ascii_whitespace() {
+                        i += 1;
+                        continue;
+                    }
 
-                while let Some(line) = line_reader.read_line() {
-                    let line = line.trim();
-                    sloc.stats.lines += 1;
+                    let info = self.comment_info;
 
-                    if line.is_empty() {
-                        sloc.stats.blanks += 1;
+                    if let Some(prefix) = info.char_prefixes.iter().find(|prefix| {
+                        rest.len() > prefix.len() && rest.starts_with(prefix.as_bytes())
+                    }) {
+                        has_code = true;
+                        i += prefix.len() + 1;
                         continue;
                     }
 
-                    if multi_line_comment {
-                        if line
-                            .ends_with(self.comment_info.multi_line_end[multi_line_comment_index])
+                    if !has_code && !has_comment {
+                        if let Some((start, end)) = info
+                            .doc_comments
+                            .iter()
+                            .find(|(start, _)| rest.starts_with(start.as_bytes()))
                         {
-                            multi_line_comment = false;
-                            multi_line_comment_index = 0;
+                            has_comment = true;
+                            self.state = LexState::Comment {
+                                end,
+                                nest: None,
+                                depth: 0,
+                            };
+                            i += start.len();
+                            continue;
                         }
-                        sloc.stats.comments += 1;
-                    } else {
-                        let mut skip_single_line_check = false;
-                        for i in 0..self.comment_info.multi_line_start.len() {
-                            if line.starts_with(self.comment_info.multi_line_start[i]) {
-                                multi_line_comment = true;
-                                multi_line_comment_index = i;
-                                sloc.stats.comments += 1;
-                            }
+                    }
 
-                            if multi_line_comment {
-                                if line.ends_with(
-                                    self.comment_info.multi_line_end[multi_line_comment_index],
-                                ) {
-                                    multi_line_comment = false;
-                                    skip_single_line_check = true;
-                                }
+                    let block_comment = info
+                        .nested
+                        .iter()
+                        .map(|comment| (comment, true))
+                        .chain(info.multi_line.iter().map(|comment| (comment, false)))
+                        .find(|((start, _), _)| rest.starts_with(start.as_bytes()));
+                    if let Some(((start, end), nested)) = block_comment {
+                        has_comment = true;
+                        self.state = LexState::Comment {
+                            end,
+                            nest: if nested { Some(start) } else { None },
+                            depth: 0,
+                        };
+                        i += start.len();
+                        continue;
+                    }
 
-                                break;
+                    if let Some((start, end)) = info
+                        .form_comments
+                        .iter()
+                        .find(|(start, _)| starts_form(rest, start.as_bytes()))
+                    {
+                        has_comment = true;
+                        self.state = LexState::Comment {
+                            end,
+                            nest: opening_bracket(end),
+                            depth: 0,
+                        };
+                        i += start.len();
+                        continue;
+                    }
+
+                    if let Some(prefix) = info
+                        .datum_comments
+                        .iter()
+                        .find(|prefix| rest.starts_with(prefix.as_bytes()))
+                    {
+                        has_comment = true;
+                        i += prefix.len();
+                        while line.get(i).is_some_and(|b| b.is_ascii_whitespace()) {
+                            i += 1;
+                        }
+                        let end = match line.get(i) {
+                            Some(b'(') => Some(")"),
+                            Some(b'[') => Some("]"),
+                            Some(b'{') => Some("}"),
+                            Some(b'"') => Some("\""),
+                            _ => None,
+                        };
+                        match end {
+                            Some(end) => {
+                                self.state = LexState::Comment {
+                                    end,
+                                    nest: opening_bracket(end),
+                                    depth: 0,
+                                };
+                                i += 1;
+                            }
+                            None => {
+                                while line.get(i).is_some_and(|&b| !ends_atom(b)) {
+                                    i += 1;
+                                }
                             }
                         }
+                        continue;
+                    }
 
-                        if !multi_line_comment && !skip_single_line_check {
-                            let is_comment = {
-                                if self.comment_info.single_line.is_empty() {
-                                    false
-                                } else {
-                                    self.comment_info
-                                        .single_line
-                                        .iter()
-                                        .filter(|a| line.starts_with(*a))
-                                        .count()
-                                        >= 1
-                                }
-                            };
+                    let starts_word = line[..i]
+                        .last()
+                        .is_none_or(|&b| b.is_ascii_whitespace() || b";|&(".contains(&b));
+                    if (starts_word || !info.word_comments)
+                        && info
+                            .single_line
+                            .iter()
+                            .any(|start| rest.starts_with(start.as_bytes()))
+                    {
+                        has_comment = true;
+                        break;
+                    }
 
-                            if is_comment {
-                                sloc.stats.comments += 1;
-                            }
+                    has_code = true;
+
+                    // The longest delimiter wins, so that `"""` isn't read as
+                    // an empty string.
+                    let escape = info.quote_escape.unwrap_or(b'\\');
+                    let quote = info
+                        .quotes
+                        .iter()
+                        .map(|quote| (quote, Some(escape)))
+                        .chain(info.verbatim_quotes.iter().map(|quote| (quote, None)))
+                        .filter(|((start, _), _)| rest.starts_with(start.as_bytes()))
+                        .min_by_key(|((start, _), _)| Reverse(start.len()));
+                    if let Some(((start, end), escape)) = quote {
+                        self.state = LexState::Quote(end, escape);
+                        i += start.len();
+                        continue;
+                    }
+
+                    if !info.heredocs.is_empty() {
+                        if arith_parens == 0 && rest.starts_with(b"((") {
+                            arith_parens = 2;
+                            i += 2;
+                            continue;
+                        }
+                        match rest[0] {
+                            b'(' if arith_parens > 0 => arith_parens += 1,
+                            b')' if arith_parens > 0 => arith_parens -= 1,
+                            _ => {}
                         }
                     }
+
+                    // As in `1<<n`, a `<<` right after an operand is a shift.
+                    let after_operand = line[..i]
+                        .last()
+                        .is_some_and(|&b| b.is_ascii_digit() || b == b')' || b == b']');
+                    if let Some((len, end)) = info
+                        .heredocs
+                        .iter()
+                        .filter(|_| arith_parens == 0 && !after_operand)
+                        .filter_map(|op| heredoc_start(rest, op.as_bytes()))
+                        .next()
+                    {
+                        if let Some(end) = end {
+                            self.heredoc_ends.push(end.to_vec());
+                        }
+                        i += len;
+                        continue;
+                    }
+
+                    if let Some(len) = info
+                        .char_quotes
+                        .iter()
+                        .filter_map(|quote| char_literal_len(rest, quote.as_bytes()))
+                        .next()
+                    {
+                        i += len;
+                        continue;
+                    }
+
+                    i += 1;
                 }
-                sloc.stats.code = sloc.stats.lines - sloc.stats.comments - sloc.stats.blanks;
-                Some(sloc)
             }
-            Err(_) => None,
         }
+
+        if has_code && has_comment {
+            LineKind::Mixed
+        } else if has_code {
+            LineKind::Code
+        } else if has_comment {
+            LineKind::Comment
+        } else {
+            LineKind::Blank
+        }
+    }
+}
+
+// Comment markers are all ASCII, so lines are classified as bytes and files
+// in legacy encodings are still counted in full.
+fn trim(line: &[u8]) -> &[u8] {
+    let start = line
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(line.len());
+    let end = line
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    &line[start..end]
+}
+
+fn opening_bracket(close: &str) -> Option<&'static str> {
+    match close {
+        ")" => Some("("),
+        "]" => Some("["),
+        "}" => Some("{"),
+        _ => None,
+    }
+}
+
+// Whether `s` starts with the form comment opener `start`, and not just with a
+// longer word that begins the same way, e.g. `(comments ...)`.
+fn starts_form(s: &[u8], start: &[u8]) -> bool {
+    if !s.starts_with(start) {
+        return false;
+    }
+    let ends_in_word = start.last().is_some_and(|b| b.is_ascii_alphanumeric());
+    match s.get(start.len()) {
+        Some(&b) if ends_in_word => b.is_ascii_whitespace() || b == b')',
+        _ => true,
+    }
+}
+
+fn ends_atom(b: u8) -> bool {
+    b.is_ascii_whitespace() || b"()[]{}\";".contains(&b)
+}
+
+// Returns the length of the here-document operator and word at the start of
+// `s`, e.g. `<<-'EOF'`, along with the word that ends the document. Here-strings
+// such as `<<<` have no end word.
+fn heredoc_start<'a>(s: &'a [u8], op: &[u8]) -> Option<(usize, Option<&'a [u8]>)> {
+    if !s.starts_with(op) {
+        return None;
+    }
+
+    let mut i = op.len();
+    // `<<<` is a here-string and `<<-` or `<<~` allow indented bodies.
+    match s.get(i) {
+        Some(b'<') => return Some((i + 1, None)),
+        Some(b'-') | Some(b'~') => i += 1,
+        _ => {}
+    }
+    while s.get(i).is_some_and(|&b| b == b' ' || b == b'\t') {
+        i += 1;
+    }
+
+    match s.get(i) {
+        Some(&quote) if quote == b'\'' || quote == b'"' => {
+            let len = s[i + 1..].iter().position(|&b| b == quote)?;
+            let word = &s[i + 1..i + 1 + len];
+            if word.is_empty() {
+                None
+            } else {
+                Some((i + len + 2, Some(word)))
+            }
+        }
+        Some(&b) if b.is_ascii_alphabetic() || b == b'_' || b == b'\\' => {
+            let start = if b == b'\\' { i + 1 } else { i };
+            let len = s[start..]
+                .iter()
+                .position(|&b| !(b.is_ascii_alphanumeric() || b == b'_'))
+                .unwrap_or(s.len() - start);
+            if len == 0 {
+                None
+            } else {
+                Some((start + len, Some(&s[start..start + len])))
+            }
+        }
+        _ => None,
+    }
+}
+
+// Returns the length of the character literal at the start of `s`, if any.
+fn char_literal_len(s: &[u8], quote: &[u8]) -> Option<usize> {
+    if !s.starts_with(quote) {
+        return None;
+    }
+
+    let body = &s[quote.len()..];
+    match body.first() {
+        Some(b'\\') => (2..body.len().min(12))
+            .find(|&i| body[i..].starts_with(quote))
+            .map(|i| quote.len() + i + quote.len()),
+        Some(&b) => {
+            let char_len = match b {
+                0x00..=0x7f => 1,
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            if body.len() > char_len && body[char_len..].starts_with(quote) {
+                Some(quote.len() + char_len + quote.len())
+            } else {
+                None
+            }
+        }
+        None => None,
+    }
+}
+
+pub struct Counter<'a> {
+    path: &'a Path,
+    lang: Lang,
+    comment_info: &'a CommentInfo,
+    mixed_lines: MixedLines,
+    invalid_utf8: bool,
+}
+
+impl<'a> Counter<'a> {
+    pub fn new(
+        path: &'a Path,
+        lang: Lang,
+        comment_info: &'a CommentInfo,
+        mixed_lines: MixedLines,
+    ) -> Self {
+        Self {
+            path,
+            lang,
+            comment_info,
+            mixed_lines,
+            invalid_utf8: false,
+        }
+    }
+
+    pub fn has_invalid_utf8(&self) -> bool {
+        self.invalid_utf8
+    }
+
+    pub fn count(&mut self) -> io::Result<Sloc> {
+        let mut f = File::open(self.path)?;
+
+        // Empty files can't be mapped, and files in procfs, sysfs or on some
+        // network filesystems report no size or refuse to be mapped, so those
+        // are read into a buffer instead.
+        let is_mappable = f.metadata().is_ok_and(|m| m.is_file() && m.len() > 0);
+        if is_mappable {
+            if let Ok(mmap) = unsafe { Mmap::map(&f) } {
+                return Ok(self.count_bytes(&mmap));
+            }
+        }
+
+        let mut buf = Vec::new();
+        f.read_to_end(&mut buf)?;
+        Ok(self.count_bytes(&buf))
+    }
+
+    pub(crate) fn count_bytes(&mut self, bytes: &[u8]) -> Sloc {
+        self.invalid_utf8 = str::from_utf8(bytes).is_err();
+
+        let mut line_reader = LineReader::new(bytes);
+        let mut lexer = Lexer::new(self.comment_info);
+        let mut sloc = Sloc::new(self.lang.clone());
+        sloc.stats.files = 1;
+
+        while let Some(raw_line) = line_reader.read_line() {
+            let line = trim(raw_line);
+            sloc.stats.lines += 1;
+
+            if line.is_empty() {
+                sloc.stats.blanks += 1;
+                continue;
+            }
+
+            if lexer.is_column_comment(raw_line) {
+                sloc.stats.comments += 1;
+                continue;
+            }
+
+            match lexer.classify(line) {
+                LineKind::Comment => sloc.stats.comments += 1,
+                LineKind::Mixed => match self.mixed_lines {
+                    MixedLines::Code => {}
+                    MixedLines::Comment => sloc.stats.comments += 1,
+                    MixedLines::Separate => sloc.stats.mixed += 1,
+                },
+                LineKind::Blank | LineKind::Code => {}
+            }
+        }
+        sloc.stats.code =
+            sloc.stats.lines - sloc.stats.comments - sloc.stats.blanks - sloc.stats.mixed;
+        sloc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{trim, Counter, Lexer, LineKind, LineReader, MixedLines};
+    use lang::{CommentInfo, Lang, Languages};
+    use std::path::Path;
+
+    fn read_lines(input: &[u8]) -> Vec<String> {
+        let mut line_reader = LineReader::new(input);
+        let mut lines = Vec::new();
+        while let Some(line) = line_reader.read_line() {
+            lines.push(String::from_utf8_lossy(line).into_owned());
+        }
+        lines
+    }
+
+    #[test]
+    fn reads_lf_terminated_lines() {
+        assert_eq!(read_lines(b"a\n\nb\n"), vec!["a", "", "b"]);
+    }
+
+    #[test]
+    fn reads_crlf_terminated_lines() {
+        assert_eq!(read_lines(b"a\r\n\r\nb\r\n"), vec!["a", "", "b"]);
+    }
+
+    #[test]
+    fn reads_cr_terminated_lines() {
+        assert_eq!(read_lines(b"a\r\rb\r"), vec!["a", "", "b"]);
+    }
+
+    #[test]
+    fn reads_final_line_without_terminator() {
+        assert_eq!(read_lines(b"a\nb"), vec!["a", "b"]);
+        assert_eq!(read_lines(b"a\r\nb"), vec!["a", "b"]);
+        assert_eq!(read_lines(b"a\rb"), vec!["a", "b"]);
+        assert_eq!(read_lines(b"a"), vec!["a"]);
+    }
+
+    #[test]
+    fn reads_lines_with_invalid_utf8() {
+        assert_eq!(
+            read_lines(b"a\n\xe9t\xe9\nb\n"),
+            vec!["a", "\u{fffd}t\u{fffd}", "b"]
+        );
+    }
+
+    #[test]
+    fn reads_nothing_from_empty_input() {
+        assert!(read_lines(b"").is_empty());
+    }
+
+    fn classify_lines(comment_info: &CommentInfo, input: &str) -> Vec<LineKind> {
+        let mut lexer = Lexer::new(comment_info);
+        input
+            .lines()
+            .map(|line| lexer.classify(trim(line.as_bytes())))
+            .collect()
+    }
+
+    fn c_like() -> CommentInfo {
+        CommentInfo {
+            single_line: vec!["//".to_owned()],
+            multi_line: vec![("/*".to_owned(), "*/".to_owned())],
+            quotes: vec![("\"".to_owned(), "\"".to_owned())],
+            char_quotes: vec!["'".to_owned()],
+            ..CommentInfo::default()
+        }
+    }
+
+    #[test]
+    fn skips_escaped_quotes() {
+        let input = "s = \"a \\\" // b\";\ns = \"\\\\\"; // c\n// d\n";
+        assert_eq!(
+            classify_lines(&c_like(), input),
+            vec![LineKind::Code, LineKind::Mixed, LineKind::Comment]
+        );
+    }
+
+    #[test]
+    fn prefers_longest_quote() {
+        let kotlin = CommentInfo {
+            verbatim_quotes: vec![("\"\"\"".to_owned(), "\"\"\"".to_owned())],
+            ..c_like()
+        };
+        let input = "val s = \"\"\"\nit's a \"quote\n// inside raw string\n\"\"\"\n// c\n";
+        assert_eq!(
+            classify_lines(&kotlin, input),
+            vec![
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Comment
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_other_escape_characters() {
+        let input = "$s = \"a`\"b\"\n<# block\n#>\n# c\n$t = 'a`'\n# c\n";
+        assert_eq!(
+            classify_lines(&bundled("PowerShell"), input),
+            vec![
+                LineKind::Code,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Code,
+                LineKind::Comment
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_raw_strings_without_escapes() {
+        let rust = CommentInfo {
+            verbatim_quotes: vec![
+                ("r#\"".to_owned(), "\"#".to_owned()),
+                ("r\"".to_owned(), "\"".to_owned()),
+            ],
+            ..c_like()
+        };
+        let input = "s = r#\"a \" // b\"#;\ns = r\"\\\"; // c\n";
+        assert_eq!(
+            classify_lines(&rust, input),
+            vec![LineKind::Code, LineKind::Mixed]
+        );
+    }
+
+    #[test]
+    fn reads_multi_line_strings() {
+        let python = CommentInfo {
+            single_line: vec!["#".to_owned()],
+            quotes: vec![
+                ("\"\"\"".to_owned(), "\"\"\"".to_owned()),
+                ("\"".to_owned(), "\"".to_owned()),
+            ],
+            ..CommentInfo::default()
+        };
+        let input = "x = \"\"\"a # b\n# c\"\"\"\n# d\n";
+        assert_eq!(
+            classify_lines(&python, input),
+            vec![LineKind::Code, LineKind::Code, LineKind::Comment]
+        );
+
+        let javascript = CommentInfo {
+            quotes: vec![("`".to_owned(), "`".to_owned())],
+            ..c_like()
+        };
+        let input = "x = `a\n// b\n${c}`\n// d\n";
+        assert_eq!(
+            classify_lines(&javascript, input),
+            vec![
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Comment
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_char_literals_but_not_lifetimes_or_digit_separators() {
+        let input = "fn f<'a>(s: &'a str) { let c = '\"'; }\n\
+                     let q = '\\''; // c\n\
+                     int n = 1'000'000; // c\n\
+                     // d\n";
+        assert_eq!(
+            classify_lines(&c_like(), input),
+            vec![
+                LineKind::Code,
+                LineKind::Mixed,
+                LineKind::Mixed,
+                LineKind::Comment
+            ]
+        );
+    }
+
+    #[test]
+    fn classifies_lines_where_comments_begin_or_end() {
+        let input = "int x = 1; /* begin\nmiddle\n*/ int y;\n/* a */ /* b */\n*/\n";
+        assert_eq!(
+            classify_lines(&c_like(), input),
+            vec![
+                LineKind::Mixed,
+                LineKind::Comment,
+                LineKind::Mixed,
+                LineKind::Comment,
+                LineKind::Code
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_mixed_lines_by_policy() {
+        let input = b"int x = 1; /* begin\nmiddle\n*/ int y;\n\nint z; // c\n";
+        let counts = |mixed_lines| {
+            let comment_info = c_like();
+            let mut counter =
+                Counter::new(Path::new("a.c"), Lang::new("C"), &comment_info, mixed_lines);
+            let stats = counter.count_bytes(input).stats;
+            (
+                stats.lines,
+                stats.code,
+                stats.comments,
+                stats.blanks,
+                stats.mixed,
+            )
+        };
+        assert_eq!(counts(MixedLines::Code), (5, 3, 1, 1, 0));
+        assert_eq!(counts(MixedLines::Comment), (5, 0, 4, 1, 0));
+        assert_eq!(counts(MixedLines::Separate), (5, 0, 1, 1, 3));
+    }
+
+    fn nested(start: &str, end: &str) -> CommentInfo {
+        CommentInfo {
+            nested: vec![(start.to_owned(), end.to_owned())],
+            ..c_like()
+        }
+    }
+
+    #[test]
+    fn nests_block_comments() {
+        let input = "/* a /* b */\nc */\nx();\n/* a /* b */ c */ x();\n";
+        assert_eq!(
+            classify_lines(&nested("/*", "*/"), input),
+            vec![
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Code,
+                LineKind::Mixed
+            ]
+        );
+
+        let input = "{- {- -} -}\nmain\n{- a {- b -}\n-} main\n";
+        assert_eq!(
+            classify_lines(&nested("{-", "-}"), input),
+            vec![
+                LineKind::Comment,
+                LineKind::Code,
+                LineKind::Comment,
+                LineKind::Mixed
+            ]
+        );
+
+        // D's `/+ +/` nest, but its `/* */` don't.
+        let input = "/+ a /+ b +/\nc +/\n/* a /* b */ x();\n";
+        assert_eq!(
+            classify_lines(&nested("/+", "+/"), input),
+            vec![LineKind::Comment, LineKind::Comment, LineKind::Mixed]
+        );
+    }
+
+    #[test]
+    fn counts_preprocessor_directives_as_code() {
+        let languages = Languages::bundled();
+        let input = "#version 450\n#define N 4 // n\n#include \"a.h\"\n// c\n";
+        for name in &["GLSL", "HLSL", "Metal", "CUDA", "OpenCL"] {
+            let lang = languages.find(name).unwrap();
+            let comment_info = languages.comment_info(&lang).unwrap();
+            assert_eq!(
+                classify_lines(comment_info, input),
+                vec![
+                    LineKind::Code,
+                    LineKind::Mixed,
+                    LineKind::Code,
+                    LineKind::Comment
+                ],
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn counts_heredoc_bodies_as_code() {
+        let shell = CommentInfo {
+            single_line: vec!["#".to_owned()],
+            heredocs: vec!["<<".to_owned()],
+            ..CommentInfo::default()
+        };
+        let input = "cat <<-'EOF' | sort # c\n\t# body\n\tEOF\n# comment\n";
+        assert_eq!(
+            classify_lines(&shell, input),
+            vec![
+                LineKind::Mixed,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Comment
+            ]
+        );
+    }
+
+    #[test]
+    fn starts_shell_comments_only_at_words() {
+        let input = "n=${#arr[@]}\necho $# ${x#pre}\necho x # c\nf;# c\n";
+        assert_eq!(
+            classify_lines(&bundled("Bash"), input),
+            vec![
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Mixed,
+                LineKind::Mixed
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_here_strings_and_shifts() {
+        let shell = CommentInfo {
+            single_line: vec!["#".to_owned()],
+            heredocs: vec!["<<".to_owned()],
+            ..CommentInfo::default()
+        };
+        let input = "cat <<<\"$x\"\necho $((1 << 2))\n# comment\n\
+                     (( mask = 1<<bit ))\n# comment\n\
+                     (( mask = mask << bit ))\n# comment\n";
+        assert_eq!(
+            classify_lines(&shell, input),
+            vec![
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Comment,
+                LineKind::Code,
+                LineKind::Comment,
+                LineKind::Code,
+                LineKind::Comment
+            ]
+        );
+    }
+
+    fn clojure() -> CommentInfo {
+        CommentInfo {
+            single_line: vec![";".to_owned()],
+            form_comments: vec![("(comment".to_owned(), ")".to_owned())],
+            datum_comments: vec!["#_".to_owned()],
+            quotes: vec![("\"".to_owned(), "\"".to_owned())],
+            char_prefixes: vec!["\\".to_owned()],
+            ..CommentInfo::default()
+        }
+    }
+
+    #[test]
+    fn counts_form_comments_as_comments() {
+        let input = "(comment\n  (f (g)))\n(f #_(g) 1)\n(comments 1)\n";
+        assert_eq!(
+            classify_lines(&clojure(), input),
+            vec![
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Mixed,
+                LineKind::Code
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_char_prefixes_as_code() {
+        let input = "(def q \\\")\n(f 1)\n; c\n(def s \\;) ; c\n";
+        assert_eq!(
+            classify_lines(&clojure(), input),
+            vec![
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Comment,
+                LineKind::Mixed
+            ]
+        );
+    }
+
+    fn bundled(name: &str) -> CommentInfo {
+        let languages = Languages::bundled();
+        let lang = languages.find(name).unwrap();
+        languages.comment_info(&lang).unwrap().clone()
+    }
+
+    #[test]
+    fn reads_lisp_and_erlang_character_literals() {
+        let input = "(define c #\\\")\n; c\n(define d #\\;)\n#| a #| b |# c |#\n";
+        for name in &["Common Lisp", "Racket", "Scheme"] {
+            assert_eq!(
+                classify_lines(&bundled(name), input),
+                vec![
+                    LineKind::Code,
+                    LineKind::Comment,
+                    LineKind::Code,
+                    LineKind::Comment
+                ],
+                "{}",
+                name
+            );
+        }
+
+        let input = "X = $\" a,\n% c\nY = $%.\n";
+        assert_eq!(
+            classify_lines(&bundled("Erlang"), input),
+            vec![LineKind::Code, LineKind::Comment, LineKind::Code]
+        );
+    }
+
+    #[test]
+    fn counts_elixir_doc_attributes_as_comments() {
+        let input = "@moduledoc \"\"\"\nDocs # here\n\"\"\"\n@doc ~S\"\"\"\nx\n\"\"\"\n\
+                     def f, do: \"\"\"\nnot docs\n\"\"\"\n";
+        assert_eq!(
+            classify_lines(&bundled("Elixir"), input),
+            vec![
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code
+            ]
+        );
+    }
+
+    #[test]
+    fn nests_ocaml_comments() {
+        let input = "(* a (* b *) c *)\nlet x = 1 (* c *)\n(* a (* b *)\nstill *)\nlet c = '\"'\n";
+        assert_eq!(
+            classify_lines(&bundled("OCaml"), input),
+            vec![
+                LineKind::Comment,
+                LineKind::Mixed,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Code
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_datum_comments_as_comments() {
+        let input = "#_foo\n#_\"str\"\n#_:kw\n#_ (f\n  [g])\n#_{:a 1}\n(f #_x)\n";
+        assert_eq!(
+            classify_lines(&clojure(), input),
+            vec![
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Mixed
+            ]
+        );
+    }
+
+    #[test]
+    fn nests_own_line_comments() {
+        let matlab = CommentInfo {
+            single_line: vec!["%".to_owned()],
+            own_line_comments: vec![("%{".to_owned(), "%}".to_owned())],
+            ..CommentInfo::default()
+        };
+        let input = "%{\n%{\nx\n%}\ny\n%}\nz = 1; %{\nw = 2\n";
+        assert_eq!(
+            classify_lines(&matlab, input),
+            vec![
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Comment,
+                LineKind::Mixed,
+                LineKind::Code
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_lines_after_invalid_utf8() {
+        let input = b"// caf\xe9\nint x;\n\n/* \xff */\nint y;\n";
+        let comment_info = c_like();
+        let mut counter = Counter::new(
+            Path::new("a.c"),
+            Lang::new("C"),
+            &comment_info,
+            MixedLines::Code,
+        );
+        let stats = counter.count_bytes(input).stats;
+        assert!(counter.has_invalid_utf8());
+        assert_eq!(
+            (stats.lines, stats.code, stats.comments, stats.blanks),
+            (5, 2, 2, 1)
+        );
+    }
+
+    #[test]
+    fn counts_column_comments_only_in_the_first_column() {
+        let fortran = CommentInfo {
+            single_line: vec!["!".to_owned()],
+            column_comments: vec!["C".to_owned(), "*".to_owned()],
+            ..CommentInfo::default()
+        };
+        let input = b"C comment\n* comment\n      CALL X\n      END ! end\n";
+        let mut counter = Counter::new(
+            Path::new("a.f"),
+            Lang::new("Fortran"),
+            &fortran,
+            MixedLines::Comment,
+        );
+        let stats = counter.count_bytes(input).stats;
+        assert_eq!((stats.code, stats.comments), (1, 3));
     }
 }
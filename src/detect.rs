@@ -0,0 +1,303 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str;
+
+// Reads the start of a file, enough to see its first line.
+pub fn read_head(path: &Path, len: usize) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(len);
+    File::open(path)?.take(len as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+// Returns the name of the interpreter named by a `#!` line, without its
+// directory, e.g. `python3.11` for `#!/usr/bin/env -S python3.11 -u`.
+pub fn shebang_interpreter(head: &[u8]) -> Option<&str> {
+    if !head.starts_with(b"#!") {
+        return None;
+    }
+    let end = head
+        .iter()
+        .position(|&b| b == b'\n' || b == b'\r')
+        .unwrap_or(head.len());
+    let line = str::from_utf8(&head[2..end]).ok()?;
+
+    let mut words = line.split_whitespace();
+    let mut program = basename(words.next()?);
+    if program == "env" {
+        // Skip env's options and variable assignments. `-S` may be followed
+        // by the command line without a space.
+        program = loop {
+            let word = words.next()?;
+            if word.starts_with("-S") && word.len() > 2 {
+                break basename(&word[2..]);
+            } else if !word.starts_with('-') && !word.contains('=') {
+                break basename(word);
+            }
+        };
+    }
+
+    if program.is_empty() {
+        None
+    } else {
+        Some(program)
+    }
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// Drops a trailing version from an interpreter name, e.g. `python3.11`
+// becomes `python`.
+pub fn strip_version(name: &str) -> &str {
+    let stripped = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let stripped = stripped.trim_end_matches('-');
+    if stripped.is_empty() {
+        name
+    } else {
+        stripped
+    }
+}
+
+// Languages whose extensions are also used by other languages.
+const AMBIGUOUS: &[&str] = &["C Header", "D", "Objective C", "Perl", "TypeScript"];
+
+pub fn is_ambiguous(lang: &str) -> bool {
+    AMBIGUOUS.contains(&lang)
+}
+
+// Picks between the languages that share an extension with `lang` by looking
+// at the start of a file. Returns `None` when `lang` is the best guess.
+pub fn disambiguate(lang: &str, head: &[u8]) -> Option<&'static str> {
+    let text = String::from_utf8_lossy(head);
+    let lines = || text.lines().map(|l| l.trim()).filter(|l| !l.is_empty());
+
+    match lang {
+        // `.h` is shared by C, C++ and Objective-C.
+        "C Header" => {
+            if lines().any(is_objc_line) {
+                Some("Objective C")
+            } else if lines().any(is_cpp_line) {
+                Some("C++ Header")
+            } else {
+                None
+            }
+        }
+
+        // `.m` is shared by Objective-C and MATLAB.
+        "Objective C" => {
+            if lines().any(|l| is_objc_line(l) || l.starts_with("#include")) {
+                None
+            } else if lines().any(is_matlab_line) {
+                Some("MATLAB")
+            } else {
+                None
+            }
+        }
+
+        // `.pl` is shared by Perl and Prolog.
+        "Perl" => {
+            if lines().any(is_perl_line) {
+                None
+            } else if lines().any(is_prolog_line) {
+                Some("Prolog")
+            } else {
+                None
+            }
+        }
+
+        // `.ts` is shared by TypeScript and Qt Linguist translations.
+        "TypeScript" => match lines().next() {
+            Some(l) if l.starts_with("<?xml") || l.starts_with("<!DOCTYPE TS") => Some("XML"),
+            _ => None,
+        },
+
+        // `.d` is shared by D and the dependency files written by compilers
+        // for make, e.g. `main.o: main.c main.h \`.
+        "D" => match lines().next() {
+            Some(l) if is_make_rule(l) => Some("Makefile"),
+            _ => None,
+        },
+
+        _ => None,
+    }
+}
+
+fn is_objc_line(line: &str) -> bool {
+    [
+        "@interface",
+        "@implementation",
+        "@protocol",
+        "@class",
+        "@property",
+        "@end",
+        "#import",
+    ]
+    .iter()
+    .any(|p| line.starts_with(p))
+}
+
+fn is_cpp_line(line: &str) -> bool {
+    let starts = [
+        "class ",
+        "namespace ",
+        "template",
+        "using ",
+        "public:",
+        "private:",
+        "protected:",
+        "virtual ",
+    ];
+    let contains = ["std::", "nullptr", "constexpr", "::~"];
+
+    // Standard C++ headers have no extension, e.g. `#include <vector>`.
+    let includes_cpp_header = line.starts_with("#include <")
+        && line.find('>').is_some_and(|end| !line[..end].contains('.'));
+
+    starts.iter().any(|p| line.starts_with(p))
+        || contains.iter().any(|p| line.contains(p))
+        || includes_cpp_header
+}
+
+fn is_matlab_line(line: &str) -> bool {
+    line.starts_with('%')
+        || line.starts_with("function ")
+        || line.starts_with("function[")
+        || line.starts_with("classdef ")
+        || line == "end"
+        || line == "endfunction"
+}
+
+fn is_perl_line(line: &str) -> bool {
+    [
+        "use strict",
+        "use warnings",
+        "my ",
+        "sub ",
+        "package ",
+        "#!",
+    ]
+    .iter()
+    .any(|p| line.starts_with(p))
+}
+
+fn is_prolog_line(line: &str) -> bool {
+    line.starts_with(":-") || line.ends_with(":-") || line.contains(") :-")
+}
+
+fn is_make_rule(line: &str) -> bool {
+    match line.find(':') {
+        Some(i) => {
+            let target = &line[..i];
+            !target.is_empty()
+                && (target.contains('.') || target.contains('/'))
+                && !target.contains(|c: char| c.is_whitespace() || c == '(' || c == '@')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disambiguate, shebang_interpreter, strip_version};
+
+    #[test]
+    fn reads_interpreter_paths() {
+        assert_eq!(shebang_interpreter(b"#!/bin/bash\necho"), Some("bash"));
+        assert_eq!(shebang_interpreter(b"#! /usr/bin/perl -w\n"), Some("perl"));
+        assert_eq!(shebang_interpreter(b"#!ruby"), Some("ruby"));
+        assert_eq!(shebang_interpreter(b"#!/bin/sh\r\n"), Some("sh"));
+    }
+
+    #[test]
+    fn reads_env_forms() {
+        assert_eq!(
+            shebang_interpreter(b"#!/usr/bin/env python3\n"),
+            Some("python3")
+        );
+        assert_eq!(
+            shebang_interpreter(b"#!/usr/bin/env -S python3.11 -u\n"),
+            Some("python3.11")
+        );
+        assert_eq!(
+            shebang_interpreter(b"#!/usr/bin/env -Snode --harmony\n"),
+            Some("node")
+        );
+        assert_eq!(
+            shebang_interpreter(b"#!/usr/bin/env -i PATH=/bin lua\n"),
+            Some("lua")
+        );
+    }
+
+    #[test]
+    fn ignores_other_first_lines() {
+        assert_eq!(shebang_interpreter(b"# comment\n"), None);
+        assert_eq!(shebang_interpreter(b"#!\n"), None);
+        assert_eq!(shebang_interpreter(b"#!/usr/bin/env\n"), None);
+        assert_eq!(shebang_interpreter(b""), None);
+    }
+
+    #[test]
+    fn strips_versions() {
+        assert_eq!(strip_version("python3.11"), "python");
+        assert_eq!(strip_version("python3"), "python");
+        assert_eq!(strip_version("perl5.36"), "perl");
+        assert_eq!(strip_version("lua-5.4"), "lua");
+        assert_eq!(strip_version("ruby"), "ruby");
+    }
+
+    #[test]
+    fn tells_headers_apart() {
+        let c = b"#include <stdio.h>\n#ifdef __cplusplus\nextern \"C\" {\n#endif\nint f(void);\n";
+        assert_eq!(disambiguate("C Header", c), None);
+        assert_eq!(
+            disambiguate("C Header", b"#include <vector>\nint f();\n"),
+            Some("C++ Header")
+        );
+        assert_eq!(
+            disambiguate("C Header", b"namespace a {\nclass B;\n}\n"),
+            Some("C++ Header")
+        );
+        assert_eq!(
+            disambiguate(
+                "C Header",
+                b"#import <Foundation/Foundation.h>\n@interface A\n@end\n"
+            ),
+            Some("Objective C")
+        );
+    }
+
+    #[test]
+    fn tells_shared_extensions_apart() {
+        assert_eq!(
+            disambiguate(
+                "Objective C",
+                b"% compute\nfunction y = f(x)\ny = x;\nend\n"
+            ),
+            Some("MATLAB")
+        );
+        assert_eq!(
+            disambiguate("Objective C", b"#import \"A.h\"\n@implementation A\n"),
+            None
+        );
+        assert_eq!(
+            disambiguate("Perl", b"parent(a, b).\nchild(X) :- parent(_, X).\n"),
+            Some("Prolog")
+        );
+        assert_eq!(disambiguate("Perl", b"use strict;\nmy $x = 1;\n"), None);
+        assert_eq!(
+            disambiguate("TypeScript", b"<?xml version=\"1.0\"?>\n<!DOCTYPE TS>\n"),
+            Some("XML")
+        );
+        assert_eq!(disambiguate("TypeScript", b"let x: number = 1;\n"), None);
+        assert_eq!(
+            disambiguate("D", b"main.o: main.c main.h \\\n  util.h\n"),
+            Some("Makefile")
+        );
+        assert_eq!(
+            disambiguate("D", b"module main;\nimport std.stdio : writeln;\n"),
+            None
+        );
+    }
+}
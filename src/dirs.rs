@@ -0,0 +1,210 @@
+use counter::Stats;
+use scanner::Summary;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct DirSloc {
+    pub path: PathBuf,
+    pub stats: Stats,
+}
+
+#[derive(Clone, Debug)]
+pub struct DirTree {
+    pub path: PathBuf,
+    pub stats: Stats,
+    pub children: Vec<DirTree>,
+}
+
+#[derive(Default)]
+struct Node {
+    stats: Stats,
+    children: BTreeMap<PathBuf, Node>,
+}
+
+impl Node {
+    fn into_tree(self, path: PathBuf) -> DirTree {
+        DirTree {
+            path,
+            stats: self.stats,
+            children: self
+                .children
+                .into_iter()
+                .map(|(path, node)| node.into_tree(path))
+                .collect(),
+        }
+    }
+}
+
+impl Summary {
+    // Totals for every directory at most `depth` levels below a scanned path.
+    // Each file is counted once, in its closest directory at that depth, so the
+    // directories add up to the overall total.
+    pub fn directories(&self, depth: usize) -> Vec<DirSloc> {
+        let mut dirs: BTreeMap<PathBuf, Stats> = BTreeMap::new();
+        for file in &self.files {
+            let (root, components) = self.split_path(&file.path);
+            let mut dir = root;
+            for component in components.into_iter().take(depth) {
+                dir = dir.join(component);
+            }
+            *dirs.entry(dir).or_default() += &file.sloc.stats;
+        }
+
+        dirs.into_iter()
+            .map(|(path, stats)| DirSloc { path, stats })
+            .collect()
+    }
+
+    // One tree per scanned path, where every directory's totals include all of
+    // its subdirectories. Directories deeper than `depth` are folded into their
+    // ancestors.
+    pub fn tree(&self, depth: Option<usize>) -> Vec<DirTree> {
+        let mut roots: BTreeMap<PathBuf, Node> = BTreeMap::new();
+        for file in &self.files {
+            let (root, components) = self.split_path(&file.path);
+            let mut node = roots.entry(root.clone()).or_default();
+            node.stats += &file.sloc.stats;
+
+            let mut dir = root;
+            for component in components.into_iter().take(depth.unwrap_or(usize::MAX)) {
+                dir = dir.join(component);
+                node = node.children.entry(dir.clone()).or_default();
+                node.stats += &file.sloc.stats;
+            }
+        }
+
+        roots
+            .into_iter()
+            .map(|(path, node)| node.into_tree(path))
+            .collect()
+    }
+
+    // Splits a file's path into the scanned path it was found under and the
+    // names of the directories between that and the file.
+    fn split_path<'a>(&self, path: &'a Path) -> (PathBuf, Vec<&'a Path>) {
+        let root = self
+            .roots
+            .iter()
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count());
+
+        match root {
+            Some(root) if root.as_path() != path => {
+                let dirs = path
+                    .strip_prefix(root)
+                    .ok()
+                    .and_then(|p| p.parent())
+                    .map_or_else(Vec::new, |p| p.iter().map(Path::new).collect());
+                (root.clone(), dirs)
+            }
+            // A file that was passed directly is counted in its directory.
+            _ => {
+                let dir = path
+                    .parent()
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .unwrap_or_else(|| Path::new("."));
+                (dir.to_path_buf(), Vec::new())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use counter::{Sloc, Stats};
+    use lang::Lang;
+    use scanner::{FileSloc, Summary};
+    use std::path::{Path, PathBuf};
+
+    fn summary(roots: &[&str], files: &[(&str, u64)]) -> Summary {
+        let mut total = Stats::default();
+        let files = files
+            .iter()
+            .map(|&(path, code)| {
+                let stats = Stats {
+                    files: 1,
+                    lines: code,
+                    code,
+                    ..Stats::default()
+                };
+                total += &stats;
+                FileSloc {
+                    path: PathBuf::from(path),
+                    sloc: Sloc {
+                        lang: Lang::new("Rust"),
+                        stats,
+                    },
+                }
+            })
+            .collect();
+        Summary {
+            roots: roots.iter().map(PathBuf::from).collect(),
+            languages: Vec::new(),
+            files,
+            total,
+        }
+    }
+
+    // Checks the totals of each directory, and that they add up to the total.
+    fn assert_directories(summary: &Summary, depth: usize, expected: &[(&str, u64)]) {
+        let dirs = summary.directories(depth);
+        let actual: Vec<_> = dirs
+            .iter()
+            .map(|dir| (dir.path.as_path(), dir.stats.code))
+            .collect();
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(path, code)| (Path::new(path), code))
+            .collect();
+        assert_eq!(actual, expected);
+        let sum: u64 = dirs.iter().map(|dir| dir.stats.code).sum();
+        assert_eq!(sum, summary.total.code);
+    }
+
+    #[test]
+    fn folds_deeper_directories_into_their_ancestors() {
+        let summary = summary(
+            &["p"],
+            &[
+                ("p/a.rs", 1),
+                ("p/src/b.rs", 2),
+                ("p/src/x/c.rs", 4),
+                ("p/src/x/y/d.rs", 8),
+            ],
+        );
+        assert_directories(&summary, 0, &[("p", 15)]);
+        assert_directories(&summary, 1, &[("p", 1), ("p/src", 14)]);
+        assert_directories(&summary, 2, &[("p", 1), ("p/src", 2), ("p/src/x", 12)]);
+
+        let tree = summary.tree(Some(1));
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].stats.code, summary.total.code);
+        assert_eq!(tree[0].children.len(), 1);
+        assert_eq!(tree[0].children[0].path, Path::new("p/src"));
+        assert_eq!(tree[0].children[0].stats.code, 14);
+        assert!(tree[0].children[0].children.is_empty());
+    }
+
+    #[test]
+    fn counts_files_passed_directly_in_their_directory() {
+        let summary = summary(&["lib.rs", "q/main.rs"], &[("lib.rs", 1), ("q/main.rs", 2)]);
+        assert_directories(&summary, 1, &[(".", 1), ("q", 2)]);
+    }
+
+    #[test]
+    fn counts_files_under_the_closest_root() {
+        let summary = summary(&["p", "p/src"], &[("p/a.rs", 1), ("p/src/b.rs", 2)]);
+        assert_directories(&summary, 1, &[("p", 1), ("p/src", 2)]);
+
+        let tree = summary.tree(None);
+        let roots: Vec<_> = tree
+            .iter()
+            .map(|root| (root.path.as_path(), root.stats.code, root.children.len()))
+            .collect();
+        assert_eq!(
+            roots,
+            vec![(Path::new("p"), 1, 0), (Path::new("p/src"), 2, 0)]
+        );
+    }
+}
@@ -1,307 +1,318 @@
+use detect;
+use globset::{self, GlobBuilder, GlobMatcher};
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
+use toml;
 
-#[derive(Clone)]
+// The definitions countcode ships with.
+const BUNDLED_LANGUAGES: &str = include_str!("languages.toml");
+
+const GLOB_CHARS: &[char] = &['*', '?', '[', '{'];
+
+#[derive(Clone, Debug, Default)]
 pub struct CommentInfo {
-    pub single_line: Arc<[&'static str]>,
-    pub multi_line_start: Arc<[&'static str]>,
-    pub multi_line_end: Arc<[&'static str]>,
+    pub single_line: Vec<String>,
+    // Whether line comments only start at the beginning of a word, as in
+    // shells where `${#arr[@]}` is code.
+    pub word_comments: bool,
+    pub multi_line: Vec<(String, String)>,
+    // Block comments that can contain other comments of the same kind.
+    pub nested: Vec<(String, String)>,
+    // Block comments that only open at the start of a line, e.g. docstrings.
+    pub doc_comments: Vec<(String, String)>,
+    // Code that is commented out as a whole, such as Clojure's `(comment ...)`.
+    // The closing bracket only ends the comment once the brackets opened
+    // inside it are closed.
+    pub form_comments: Vec<(String, String)>,
+    // Prefixes that comment out the one form after them, whether a bracketed
+    // list, a string or an atom, such as Clojure's `#_`.
+    pub datum_comments: Vec<String>,
+    // Block comments whose delimiters only count on a line of their own, like
+    // MATLAB's `%{` and `%}`. These nest.
+    pub own_line_comments: Vec<(String, String)>,
+    // Markers that make a line a comment only in its first column, like `C`
+    // in fixed-form Fortran.
+    pub column_comments: Vec<String>,
+    // String delimiters whose contents may contain escapes.
+    pub quotes: Vec<(String, String)>,
+    // The character that escapes the next one inside `quotes`, a backslash
+    // unless given, e.g. the backtick in PowerShell.
+    pub quote_escape: Option<u8>,
+    // String delimiters whose contents are taken as-is (raw strings).
+    pub verbatim_quotes: Vec<(String, String)>,
+    // Character literal delimiters. These only count as a literal when they
+    // close right after a single (possibly escaped) character, so that Rust
+    // lifetimes or C++ digit separators are left alone.
+    pub char_quotes: Vec<String>,
+    // Prefixes that make the one character after them a literal, like `\` in
+    // Clojure, so that `\"` doesn't open a string.
+    pub char_prefixes: Vec<String>,
+    // Here-document operators, e.g. `<<` in shells. The lines up to the one
+    // holding only the word after the operator are counted as code.
+    pub heredocs: Vec<String>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
-pub enum Lang {
-    Asm,
-    C,
-    CHeader,
-    Cpp,
-    CSharp,
-    CppHeader,
-    Css,
-    D,
-    FSharp,
-    Go,
-    Haskell,
-    Html,
-    Java,
-    JavaScript,
-    Json,
-    Kotlin,
-    Lua,
-    Makefile,
-    Markdown,
-    ObjectiveC,
-    Perl,
-    PlainText,
-    Python,
-    Ruby,
-    Rust,
-    Swift,
-    Toml,
-    TypeScript,
-    Xml,
-    Yaml,
+// A language, identified by the name it is displayed with.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Lang(Arc<str>);
+
+impl Lang {
+    pub fn new(name: &str) -> Self {
+        Lang(name.into())
+    }
 
-    Total,
+    pub fn name(&self) -> &str {
+        &self.0
+    }
 }
 
-impl Lang {
-    pub fn extensions() -> HashMap<&'static str, Lang> {
-        let mut extensions = HashMap::new();
+impl fmt::Display for Lang {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
-        extensions.insert("s", Lang::Asm);
-        extensions.insert("S", Lang::Asm);
-        extensions.insert("asm", Lang::Asm);
-        extensions.insert("C", Lang::Cpp);
-        extensions.insert("c", Lang::C);
-        extensions.insert("c++", Lang::Cpp);
-        extensions.insert("cc", Lang::Cpp);
-        extensions.insert("cpp", Lang::Cpp);
-        extensions.insert("cs", Lang::CSharp);
-        extensions.insert("css", Lang::Css);
-        extensions.insert("cxx", Lang::Cpp);
-        extensions.insert("d", Lang::D);
-        extensions.insert("di", Lang::D);
-        extensions.insert("fs", Lang::FSharp);
-        extensions.insert("go", Lang::Go);
-        extensions.insert("h", Lang::CHeader);
-        extensions.insert("h++", Lang::CppHeader);
-        extensions.insert("hh", Lang::CppHeader);
-        extensions.insert("hpp", Lang::CppHeader);
-        extensions.insert("hs", Lang::Haskell);
-        extensions.insert("htm", Lang::Html);
-        extensions.insert("html", Lang::Html);
-        extensions.insert("hxx", Lang::CppHeader);
-        extensions.insert("java", Lang::Java);
-        extensions.insert("js", Lang::JavaScript);
-        extensions.insert("json", Lang::Json);
-        extensions.insert("kt", Lang::Kotlin);
-        extensions.insert("lua", Lang::Lua);
-        extensions.insert("m", Lang::ObjectiveC);
-        extensions.insert("mk", Lang::Makefile);
-        extensions.insert("md", Lang::Markdown);
-        extensions.insert("pl", Lang::Perl);
-        extensions.insert("py", Lang::Python);
-        extensions.insert("py3", Lang::Python);
-        extensions.insert("rb", Lang::Ruby);
-        extensions.insert("rs", Lang::Rust);
-        extensions.insert("swift", Lang::Swift);
-        extensions.insert("toml", Lang::Toml);
-        extensions.insert("ts", Lang::TypeScript);
-        extensions.insert("txt", Lang::PlainText);
-        extensions.insert("xml", Lang::Xml);
-        extensions.insert("yaml", Lang::Yaml);
-        extensions.insert("yml", Lang::Yaml);
+// One `[[language]]` entry of a definitions file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LanguageDef {
+    pub name: String,
+    pub extensions: Vec<String>,
+    // Exact names or globs such as `Dockerfile.*`, matched ahead of
+    // extensions. Case matters, so that a `build` script isn't taken for a
+    // Bazel `BUILD` file.
+    pub filenames: Vec<String>,
+    // Programs named on a `#!` line, with or without a version after them.
+    pub interpreters: Vec<String>,
+    pub line_comments: Vec<String>,
+    pub word_comments: bool,
+    pub block_comments: Vec<(String, String)>,
+    pub nested_comments: Vec<(String, String)>,
+    pub doc_comments: Vec<(String, String)>,
+    pub form_comments: Vec<(String, String)>,
+    pub datum_comments: Vec<String>,
+    pub own_line_comments: Vec<(String, String)>,
+    pub column_comments: Vec<String>,
+    pub quotes: Vec<(String, String)>,
+    pub quote_escape: Option<char>,
+    pub verbatim_quotes: Vec<(String, String)>,
+    pub char_quotes: Vec<String>,
+    pub char_prefixes: Vec<String>,
+    pub heredocs: Vec<String>,
+}
 
-        extensions
+impl LanguageDef {
+    pub fn comment_info(&self) -> CommentInfo {
+        CommentInfo {
+            single_line: self.line_comments.clone(),
+            word_comments: self.word_comments,
+            multi_line: self.block_comments.clone(),
+            nested: self.nested_comments.clone(),
+            doc_comments: self.doc_comments.clone(),
+            form_comments: self.form_comments.clone(),
+            datum_comments: self.datum_comments.clone(),
+            own_line_comments: self.own_line_comments.clone(),
+            column_comments: self.column_comments.clone(),
+            quotes: self.quotes.clone(),
+            // Only ASCII escapes can be matched byte by byte.
+            quote_escape: self.quote_escape.filter(char::is_ascii).map(|c| c as u8),
+            verbatim_quotes: self.verbatim_quotes.clone(),
+            char_quotes: self.char_quotes.clone(),
+            char_prefixes: self.char_prefixes.clone(),
+            heredocs: self.heredocs.clone(),
+        }
     }
+}
 
-    pub fn comment_info() -> HashMap<Lang, CommentInfo> {
-        let mut comment_info = HashMap::new();
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definitions {
+    #[serde(default)]
+    language: Vec<LanguageDef>,
+}
 
-        let cpp_style_comment = {
-            let single_line = Arc::new(["//"]);
-            let multi_line_start = Arc::new(["/*"]);
-            let multi_line_end = Arc::new(["*/"]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
-            }
-        };
+pub fn parse_definitions(s: &str) -> Result<Vec<LanguageDef>, toml::de::Error> {
+    toml::from_str::<Definitions>(s).map(|d| d.language)
+}
 
-        comment_info.insert(Lang::C, cpp_style_comment.clone());
-        comment_info.insert(Lang::CHeader, cpp_style_comment.clone());
-        comment_info.insert(Lang::CSharp, cpp_style_comment.clone());
-        comment_info.insert(Lang::Cpp, cpp_style_comment.clone());
-        comment_info.insert(Lang::CppHeader, cpp_style_comment.clone());
-        comment_info.insert(Lang::Css, cpp_style_comment.clone());
-        comment_info.insert(Lang::D, cpp_style_comment.clone());
-        comment_info.insert(Lang::Go, cpp_style_comment.clone());
-        comment_info.insert(Lang::Java, cpp_style_comment.clone());
-        comment_info.insert(Lang::JavaScript, cpp_style_comment.clone());
-        comment_info.insert(Lang::Kotlin, cpp_style_comment.clone());
-        comment_info.insert(Lang::Markdown, cpp_style_comment.clone());
-        comment_info.insert(Lang::Rust, cpp_style_comment.clone());
-        comment_info.insert(Lang::Toml, cpp_style_comment.clone());
-        comment_info.insert(Lang::TypeScript, cpp_style_comment.clone());
-        comment_info.insert(Lang::ObjectiveC, cpp_style_comment.clone());
-        comment_info.insert(Lang::Swift, cpp_style_comment);
+// Maps file names and extensions to languages and languages to their syntax.
+#[derive(Clone, Debug, Default)]
+pub struct Languages {
+    extensions: HashMap<String, Lang>,
+    filenames: HashMap<String, Lang>,
+    filename_globs: Vec<(String, GlobMatcher, Lang)>,
+    interpreters: HashMap<String, Lang>,
+    comment_info: HashMap<Lang, CommentInfo>,
+}
 
-        let py_style_comment = {
-            let single_line = Arc::new(["#"]);
-            let multi_line_start = Arc::new(["\"\"\""]);
-            let multi_line_end = Arc::new(["\"\"\""]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
-            }
-        };
-        comment_info.insert(Lang::Python, py_style_comment);
+impl Languages {
+    pub fn bundled() -> Self {
+        let mut languages = Languages::default();
+        for def in parse_definitions(BUNDLED_LANGUAGES).expect("bundled languages are valid") {
+            languages
+                .add(&def)
+                .expect("bundled filename globs are valid");
+        }
+        languages
+    }
 
-        let html_style_comment = {
-            let single_line = Arc::new([]);
-            let multi_line_start = Arc::new(["<!--"]);
-            let multi_line_end = Arc::new(["-->"]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
+    // A definition with the name of a known language replaces its syntax and
+    // adds to its extensions and file names.
+    pub fn add(&mut self, def: &LanguageDef) -> Result<Lang, globset::Error> {
+        let lang = self.find(&def.name).unwrap_or_else(|| Lang::new(&def.name));
+        for ext in &def.extensions {
+            self.extensions.insert(ext.clone(), lang.clone());
+        }
+        for name in &def.filenames {
+            if name.contains(|c| GLOB_CHARS.contains(&c)) {
+                let matcher = GlobBuilder::new(name)
+                    .literal_separator(true)
+                    .build()?
+                    .compile_matcher();
+                self.filename_globs
+                    .push((name.clone(), matcher, lang.clone()));
+            } else {
+                self.filenames.insert(name.clone(), lang.clone());
             }
-        };
-        comment_info.insert(Lang::Html, html_style_comment.clone());
-        comment_info.insert(Lang::Xml, html_style_comment);
+        }
+        for name in &def.interpreters {
+            self.interpreters.insert(name.clone(), lang.clone());
+        }
+        self.comment_info.insert(lang.clone(), def.comment_info());
+        Ok(lang)
+    }
 
-        let ruby_style_comment = {
-            let single_line = Arc::new(["#"]);
-            let multi_line_start = Arc::new(["=begin"]);
-            let multi_line_end = Arc::new(["=end"]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
-            }
-        };
-        comment_info.insert(Lang::Ruby, ruby_style_comment);
+    // Looks up a language by its name, ignoring case.
+    pub fn find(&self, name: &str) -> Option<Lang> {
+        self.find_ref(name).cloned()
+    }
 
-        let perl_style_comment = {
-            let single_line = Arc::new(["#"]);
-            let multi_line_start = Arc::new(["=begin"]);
-            let multi_line_end = Arc::new(["=cut"]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
-            }
-        };
-        comment_info.insert(Lang::Perl, perl_style_comment);
+    pub(crate) fn find_ref(&self, name: &str) -> Option<&Lang> {
+        self.comment_info
+            .keys()
+            .find(|lang| lang.name().eq_ignore_ascii_case(name))
+    }
 
-        let yaml_style_comment = {
-            let single_line = Arc::new(["#"]);
-            let multi_line_start = Arc::new([]);
-            let multi_line_end = Arc::new([]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
-            }
-        };
-        comment_info.insert(Lang::Yaml, yaml_style_comment.clone());
-        comment_info.insert(Lang::Makefile, yaml_style_comment);
+    pub fn by_extension(&self, ext: &str) -> Option<&Lang> {
+        self.extensions.get(ext)
+    }
 
-        let haskell_style_comment = {
-            let single_line = Arc::new(["--"]);
-            let multi_line_start = Arc::new(["{-"]);
-            let multi_line_end = Arc::new(["-}"]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
-            }
-        };
-        comment_info.insert(Lang::Haskell, haskell_style_comment);
+    pub fn by_filename(&self, name: &str) -> Option<&Lang> {
+        // Globs added later win, as exact names do.
+        self.filenames.get(name).or_else(|| {
+            self.filename_globs
+                .iter()
+                .rev()
+                .find(|(_, matcher, _)| matcher.is_match(name))
+                .map(|(_, _, lang)| lang)
+        })
+    }
 
-        let lua_style_comment = {
-            let single_line = Arc::new(["--"]);
-            let multi_line_start = Arc::new(["--[["]);
-            let multi_line_end = Arc::new(["--]]"]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
-            }
-        };
-        comment_info.insert(Lang::Lua, lua_style_comment);
+    pub fn by_interpreter(&self, name: &str) -> Option<&Lang> {
+        self.interpreters
+            .get(name)
+            .or_else(|| self.interpreters.get(detect::strip_version(name)))
+    }
 
-        let json_style_comment = {
-            let single_line = Arc::new([]);
-            let multi_line_start = Arc::new([]);
-            let multi_line_end = Arc::new([]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
-            }
-        };
-        comment_info.insert(Lang::Json, json_style_comment);
+    pub fn comment_info(&self, lang: &Lang) -> Option<&CommentInfo> {
+        self.comment_info.get(lang)
+    }
 
-        let plain_txt_style_comment = {
-            let single_line = Arc::new([]);
-            let multi_line_start = Arc::new([]);
-            let multi_line_end = Arc::new([]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
-            }
-        };
-        comment_info.insert(Lang::PlainText, plain_txt_style_comment);
+    // All languages with their extensions and file names, sorted by name.
+    pub fn list(&self) -> Vec<(Lang, Vec<&str>, Vec<&str>)> {
+        let mut list: Vec<_> = self
+            .comment_info
+            .keys()
+            .map(|lang| {
+                let mut extensions: Vec<_> = self
+                    .extensions
+                    .iter()
+                    .filter(|&(_, l)| l == lang)
+                    .map(|(ext, _)| ext.as_str())
+                    .collect();
+                let mut filenames: Vec<_> = self
+                    .filenames
+                    .iter()
+                    .filter(|&(_, l)| l == lang)
+                    .map(|(name, _)| name.as_str())
+                    .chain(
+                        self.filename_globs
+                            .iter()
+                            .filter(|&(_, _, l)| l == lang)
+                            .map(|(glob, _, _)| glob.as_str()),
+                    )
+                    .collect();
+                extensions.sort();
+                filenames.sort();
+                (lang.clone(), extensions, filenames)
+            })
+            .collect();
+        list.sort_by(|a, b| a.0.cmp(&b.0));
+        list
+    }
+}
 
-        let fs_style_comment = {
-            let single_line = Arc::new(["//"]);
-            let multi_line_start = Arc::new(["(*"]);
-            let multi_line_end = Arc::new(["*)"]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
-            }
-        };
-        comment_info.insert(Lang::FSharp, fs_style_comment);
+#[cfg(test)]
+mod tests {
+    use super::{parse_definitions, LanguageDef, Languages, BUNDLED_LANGUAGES};
+    use std::collections::HashSet;
 
-        let asm_style_comment = {
-            let single_line = Arc::new([";", "#", "@", "//"]);
-            let multi_line_start = Arc::new(["/*"]);
-            let multi_line_end = Arc::new(["*/"]);
-            CommentInfo {
-                single_line,
-                multi_line_start,
-                multi_line_end,
+    #[test]
+    fn bundled_languages_have_unique_names_and_extensions() {
+        let defs = parse_definitions(BUNDLED_LANGUAGES).unwrap();
+        let mut names = HashSet::new();
+        let mut extensions = HashSet::new();
+        for def in &defs {
+            assert!(names.insert(def.name.to_lowercase()), "{}", def.name);
+            for ext in &def.extensions {
+                assert!(extensions.insert(ext), "{}", ext);
             }
-        };
-        comment_info.insert(Lang::Asm, asm_style_comment);
+        }
+    }
 
-        comment_info
+    #[test]
+    fn matches_filenames_and_globs() {
+        let languages = Languages::bundled();
+        let name = |filename| languages.by_filename(filename).map(|l| l.name().to_owned());
+        assert_eq!(name("CMakeLists.txt"), Some("CMake".to_owned()));
+        assert_eq!(name("Dockerfile"), Some("Dockerfile".to_owned()));
+        assert_eq!(name("Dockerfile.dev"), Some("Dockerfile".to_owned()));
+        assert_eq!(name("index.d.ts"), Some("TypeScript".to_owned()));
+        assert_eq!(name("BUILD"), Some("Starlark".to_owned()));
+        assert_eq!(name("build"), None);
+        assert_eq!(name("MAKEFILE"), Some("Makefile".to_owned()));
+        assert_eq!(name("main.rs"), None);
     }
-}
 
-impl fmt::Display for Lang {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use self::Lang::*;
-        match *self {
-            Asm => write!(f, "Assembly"),
-            C => write!(f, "C"),
-            CHeader => write!(f, "C Header"),
-            CSharp => write!(f, "C#"),
-            Cpp => write!(f, "C++"),
-            CppHeader => write!(f, "C++ Header"),
-            Css => write!(f, "CSS"),
-            D => write!(f, "D"),
-            FSharp => write!(f, "F#"),
-            Go => write!(f, "Go"),
-            Haskell => write!(f, "Haskell"),
-            Html => write!(f, "HTML"),
-            Java => write!(f, "Java"),
-            JavaScript => write!(f, "JavaScript"),
-            Json => write!(f, "JSON"),
-            Kotlin => write!(f, "Kotlin"),
-            Lua => write!(f, "Lua"),
-            Makefile => write!(f, "Makefile"),
-            Markdown => write!(f, "Markdown"),
-            Perl => write!(f, "Perl"),
-            PlainText => write!(f, "Plain Text"),
-            Python => write!(f, "Python"),
-            Ruby => write!(f, "Ruby"),
-            Rust => write!(f, "Rust"),
-            Swift => write!(f, "Swift"),
-            Toml => write!(f, "TOML"),
-            TypeScript => write!(f, "TypeScript"),
-            Xml => write!(f, "XML"),
-            Yaml => write!(f, "YAML"),
-            ObjectiveC => write!(f, "Objective C"),
+    #[test]
+    fn maps_gpu_extensions_to_their_own_languages() {
+        let languages = Languages::bundled();
+        let name = |ext| languages.by_extension(ext).map(|l| l.name().to_owned());
+        assert_eq!(name("cu"), Some("CUDA".to_owned()));
+        assert_eq!(name("cuh"), Some("CUDA".to_owned()));
+        assert_eq!(name("cl"), Some("OpenCL".to_owned()));
+        assert_eq!(name("frag"), Some("GLSL".to_owned()));
+        assert_eq!(name("cpp"), Some("C++".to_owned()));
+    }
 
-            Total => write!(f, "TOTAL"),
-        }
+    #[test]
+    fn merges_definitions_of_known_languages() {
+        let mut languages = Languages::bundled();
+        let def = LanguageDef {
+            name: "rust".to_owned(),
+            extensions: vec!["rsx".to_owned()],
+            line_comments: vec!["#".to_owned()],
+            ..LanguageDef::default()
+        };
+        let lang = languages.add(&def).unwrap();
+
+        // The existing name is kept and old extensions still map to it.
+        assert_eq!(lang.name(), "Rust");
+        assert_eq!(languages.by_extension("rsx"), Some(&lang));
+        assert_eq!(languages.by_extension("rs"), Some(&lang));
+        let comment_info = languages.comment_info(&lang).unwrap();
+        assert_eq!(comment_info.single_line, vec!["#".to_owned()]);
+        assert!(comment_info.nested.is_empty());
     }
 }
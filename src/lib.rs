@@ -0,0 +1,36 @@
+//! A library for counting lines of code, comments and blanks very quickly.
+//!
+//! ```no_run
+//! use countcode::Scanner;
+//!
+//! let mut scanner = Scanner::new();
+//! let summary = scanner.scan(&["src".to_owned()]);
+//! for sloc in &summary.languages {
+//!     println!("{}: {} lines of code", sloc.lang, sloc.stats.code);
+//! }
+//! println!("{} lines of code in total", summary.total.code);
+//! ```
+
+extern crate globset;
+extern crate ignore;
+extern crate memmap;
+extern crate rayon;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate toml;
+
+pub mod config;
+pub mod counter;
+mod detect;
+pub mod dirs;
+pub mod lang;
+mod notebook;
+pub mod scanner;
+
+pub use config::{Config, Thresholds};
+pub use counter::{Counter, MixedLines, Sloc, Stats};
+pub use dirs::{DirSloc, DirTree};
+pub use lang::{CommentInfo, Lang, LanguageDef, Languages};
+pub use scanner::{ErrorKind, FileError, FileSloc, Scanner, SortKey, SortOrder, Summary};
@@ -1,158 +1,298 @@
-extern crate ignore;
-extern crate memmap;
+extern crate clap;
+extern crate countcode;
 extern crate rayon;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
-mod counter;
-mod lang;
-mod scanner;
+mod cli;
+mod output;
 
-use counter::SlocStr;
-use scanner::Scanner;
-use std::collections::HashSet;
+use clap::ArgMatches;
+use countcode::config;
+use countcode::{Config, MixedLines, Scanner, SortKey, SortOrder, Stats, Summary, Thresholds};
+use output::{print_safe, Column, Format, Options};
 use std::env;
-use std::io::{self, StdoutLock, Write};
+use std::io;
+use std::path::Path;
+use std::process;
 
 fn main() {
-    let mut args: Vec<_> = env::args().skip(1).collect();
-    if args.is_empty() {
+    let matches = cli::build_app().get_matches();
+
+    if let Some(matches) = matches.subcommand_matches("languages") {
+        let config = read_config(matches, &env::current_dir().unwrap());
+        let scanner = build_scanner(matches, &config);
+        print_languages(&scanner);
+        return;
+    }
+
+    if let Some(threads) = matches.value_of("threads") {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.parse().unwrap())
+            .build_global()
+            .unwrap();
+    }
+
+    // Keep the paths in the order they were given, dropping duplicates.
+    let mut paths: Vec<String> = Vec::new();
+    for path in matches.values_of("paths").into_iter().flatten() {
+        if !paths.iter().any(|p| p == path) {
+            paths.push(path.to_owned());
+        }
+    }
+    if paths.is_empty() {
         let path = env::current_dir().unwrap();
-        args.push(path.to_str().unwrap().into());
+        paths.push(path.to_str().unwrap().into());
     }
 
-    let args: HashSet<_> = args.drain(..).collect();
+    let config = read_config(&matches, Path::new(&paths[0]));
+    let mut scanner = build_scanner(&matches, &config);
 
-    let mut scanner = Scanner::new();
-    let slocs = scanner.scan(args);
-    pretty_print(slocs);
-}
+    // Options given on the command line win over the configuration file.
+    let mut mixed_lines = config.mixed.unwrap_or(MixedLines::Code);
+    if matches.occurrences_of("mixed") > 0 {
+        mixed_lines = match matches.value_of("mixed").unwrap() {
+            "comment" => MixedLines::Comment,
+            "separate" => MixedLines::Separate,
+            _ => MixedLines::Code,
+        };
+        scanner.set_mixed_lines(mixed_lines);
+    }
+
+    if matches.occurrences_of("sort") > 0 {
+        let sort_key = match matches.value_of("sort").unwrap() {
+            "name" => SortKey::Name,
+            "files" => SortKey::Files,
+            "code" => SortKey::Code,
+            "comments" => SortKey::Comments,
+            "blanks" => SortKey::Blanks,
+            _ => SortKey::Lines,
+        };
+        scanner.set_sort_key(sort_key);
+        scanner.set_sort_order(sort_key.default_order());
+    }
+    match matches.value_of("order") {
+        Some("asc") => scanner.set_sort_order(SortOrder::Ascending),
+        Some("desc") => scanner.set_sort_order(SortOrder::Descending),
+        _ => {}
+    }
 
-fn pretty_print(slocs: Vec<SlocStr>) {
-    let mut lang_len = 10;
-    let mut files_len = 10;
-    let mut lines_len = 10;
-    let mut code_len = 10;
-    let mut comments_len = 10;
-    let mut blanks_len = 10;
-
-    for sloc in &slocs {
-        if lang_len < sloc.lang.len() {
-            lang_len = sloc.lang.len();
+    if matches.is_present("hidden") {
+        scanner.set_hidden(true);
+    } else if matches.is_present("no-hidden") {
+        scanner.set_hidden(false);
+    }
+    if matches.is_present("follow") {
+        scanner.set_follow_links(true);
+    } else if matches.is_present("no-follow") {
+        scanner.set_follow_links(false);
+    }
+    scanner.set_no_ignore(matches.is_present("no-ignore"));
+    scanner.set_no_ignore_vcs(matches.is_present("no-ignore-vcs"));
+    if let Some(n) = matches.value_of("max-depth") {
+        scanner.set_max_depth(Some(n.parse().unwrap()));
+    }
+    for glob in matches.values_of("include").into_iter().flatten() {
+        if let Err(err) = scanner.add_include(glob) {
+            eprintln!("countcode: invalid include pattern '{}': {}", glob, err);
+            process::exit(2);
         }
-        if files_len < sloc.files.len() {
-            files_len = sloc.files.len();
+    }
+    for glob in matches.values_of("exclude").into_iter().flatten() {
+        if let Err(err) = scanner.add_exclude(glob) {
+            eprintln!("countcode: invalid exclude pattern '{}': {}", glob, err);
+            process::exit(2);
+        }
+    }
+
+    let format = if matches.occurrences_of("output") > 0 {
+        matches.value_of("output").unwrap()
+    } else {
+        config.output.as_ref().map_or("table", |f| f.as_str())
+    };
+    let format = match format {
+        "table" => Format::Table,
+        "json" => Format::Json,
+        "csv" => Format::Csv,
+        "tsv" => Format::Tsv,
+        f => {
+            eprintln!("countcode: unknown output format '{}' in configuration", f);
+            process::exit(2);
         }
-        if lines_len < sloc.lines.len() {
-            lines_len = sloc.lines.len();
+    };
+
+    let columns = match matches.values_of("columns") {
+        Some(columns) => Some(columns.filter_map(Column::from_name).collect()),
+        None => config.columns.as_ref().map(|columns| {
+            columns
+                .iter()
+                .map(|c| {
+                    Column::from_name(c).unwrap_or_else(|| {
+                        eprintln!("countcode: unknown column '{}' in configuration", c);
+                        process::exit(2);
+                    })
+                })
+                .collect()
+        }),
+    };
+
+    let summary = scanner.scan(&paths);
+
+    let options = Options {
+        format,
+        columns,
+        show_mixed: mixed_lines == MixedLines::Separate,
+        show_total: !matches.is_present("no-total"),
+    };
+
+    if matches.is_present("dirs") {
+        let depth = matches.value_of("depth").map_or(1, |n| n.parse().unwrap());
+        output::print_directories(&summary.directories(depth), &summary.total, &options);
+    } else if matches.is_present("tree") {
+        let depth = matches.value_of("depth").map(|n| n.parse().unwrap());
+        output::print_tree(&summary.tree(depth), &summary.total, &options);
+    } else if matches.is_present("files") {
+        let langs: Vec<String> = matches
+            .values_of("lang")
+            .into_iter()
+            .flatten()
+            .map(|l| l.to_lowercase())
+            .collect();
+        let min_lines: u64 = matches
+            .value_of("min-lines")
+            .map_or(0, |n| n.parse().unwrap());
+
+        let files: Vec<_> = summary
+            .files
+            .iter()
+            .filter(|f| langs.is_empty() || langs.contains(&f.sloc.lang.to_string().to_lowercase()))
+            .filter(|f| f.sloc.stats.lines >= min_lines)
+            .collect();
+        let mut total = Stats::default();
+        for f in &files {
+            total += &f.sloc.stats;
         }
-        if code_len < sloc.code.len() {
-            code_len = sloc.code.len();
+        output::print_files(&files, &total, &options);
+    } else {
+        output::print_languages(&summary, &options);
+    }
+
+    if matches.is_present("report-non-utf8") && !scanner.non_utf8_files().is_empty() {
+        eprintln!("Files with non-UTF-8 content:");
+        for path in scanner.non_utf8_files() {
+            eprintln!("  {}", path.display());
         }
-        if comments_len < sloc.comments.len() {
-            comments_len = sloc.comments.len();
+    }
+
+    let errors = scanner.errors();
+    if !errors.is_empty() {
+        if matches.is_present("errors") {
+            eprintln!("Files that could not be counted:");
+            for err in errors {
+                eprintln!("  {}", err);
+            }
+        } else {
+            eprintln!(
+                "countcode: {} file(s) could not be counted (use --errors to list them)",
+                errors.len()
+            );
         }
-        if blanks_len < sloc.blanks.len() {
-            blanks_len = sloc.blanks.len();
+
+        if matches.is_present("fail-on-error") {
+            process::exit(1);
         }
     }
 
-    lang_len += 1;
-    files_len += 1;
-    lines_len += 1;
-    code_len += 1;
-    comments_len += 1;
-    blanks_len += 1;
+    let violations = check_thresholds(&config.thresholds, &summary);
+    if !violations.is_empty() {
+        eprintln!("Thresholds not met:");
+        for violation in &violations {
+            eprintln!("  {}", violation);
+        }
+        process::exit(1);
+    }
+}
 
-    let stdout = io::stdout();
-    let mut stdout_handle = stdout.lock();
+fn exit_with_config_error(err: &config::Error) -> ! {
+    eprintln!("countcode: {}", err);
+    process::exit(2);
+}
 
-    let total_len = lang_len + files_len + lines_len + code_len + comments_len + blanks_len;
-    for _ in 0..total_len + 7 {
-        print_safe(&mut stdout_handle, "=");
-    }
-    print_safe(&mut stdout_handle, "\n");
-
-    print_safe(
-        &mut stdout_handle,
-        &format!(
-            " {:<w0$} {:>w1$} {:>w2$} {:>w3$} {:>w4$} {:>w5$} \n",
-            "Language",
-            "Files",
-            "Lines",
-            "Code",
-            "Comments",
-            "Blanks",
-            w0 = lang_len,
-            w1 = files_len,
-            w2 = lines_len,
-            w3 = code_len,
-            w4 = comments_len,
-            w5 = blanks_len,
-        ),
-    );
-
-    for _ in 0..total_len + 7 {
-        print_safe(&mut stdout_handle, "-");
-    }
-    print_safe(&mut stdout_handle, "\n");
-
-    let len = slocs.len();
-    for sloc in slocs.iter().take(len - 1) {
-        print_safe(
-            &mut stdout_handle,
-            &format!(
-                " {:<w0$} {:>w1$} {:>w2$} {:>w3$} {:>w4$} {:>w5$} \n",
-                sloc.lang,
-                sloc.files,
-                sloc.lines,
-                sloc.code,
-                sloc.comments,
-                sloc.blanks,
-                w0 = lang_len,
-                w1 = files_len,
-                w2 = lines_len,
-                w3 = code_len,
-                w4 = comments_len,
-                w5 = blanks_len,
-            ),
-        );
+fn check_thresholds(thresholds: &Thresholds, summary: &Summary) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    if let Some(max) = thresholds.max_file_lines {
+        for file in summary.files.iter().filter(|f| f.sloc.stats.lines > max) {
+            violations.push(format!(
+                "{}: {} lines (max-file-lines is {})",
+                file.path.display(),
+                file.sloc.stats.lines,
+                max
+            ));
+        }
+    }
+
+    if let Some(max) = thresholds.max_total_code {
+        if summary.total.code > max {
+            violations.push(format!(
+                "{} lines of code in total (max-total-code is {})",
+                summary.total.code, max
+            ));
+        }
     }
 
-    for _ in 0..total_len + 7 {
-        print_safe(&mut stdout_handle, "=");
-    }
-    print_safe(&mut stdout_handle, "\n");
-
-    let sloc = slocs.get(len - 1).unwrap();
-    print_safe(
-        &mut stdout_handle,
-        &format!(
-            " {:<w0$} {:>w1$} {:>w2$} {:>w3$} {:>w4$} {:>w5$} \n",
-            sloc.lang,
-            sloc.files,
-            sloc.lines,
-            sloc.code,
-            sloc.comments,
-            sloc.blanks,
-            w0 = lang_len,
-            w1 = files_len,
-            w2 = lines_len,
-            w3 = code_len,
-            w4 = comments_len,
-            w5 = blanks_len,
-        ),
-    );
-
-    for _ in 0..total_len + 7 {
-        print_safe(&mut stdout_handle, "=");
-    }
-    print_safe(&mut stdout_handle, "\n");
+    if let Some(min) = thresholds.min_comment_ratio {
+        let ratio = if summary.total.code == 0 {
+            0.0
+        } else {
+            summary.total.comments as f64 / summary.total.code as f64
+        };
+        if ratio < min {
+            violations.push(format!(
+                "comment ratio is {:.2} (min-comment-ratio is {})",
+                ratio, min
+            ));
+        }
+    }
+
+    violations
 }
 
-fn print_safe(handle: &mut StdoutLock, s: &str) {
-    match handle.write(s.as_bytes()) {
-        Ok(_) => {}
-        Err(_) => {
-            std::process::exit(0);
+// The config file named by `--config`, or else the nearest one above `start`.
+fn read_config(matches: &ArgMatches, start: &Path) -> Config {
+    if matches.is_present("no-config") {
+        Config::default()
+    } else if let Some(path) = matches.value_of("config") {
+        Config::load(Path::new(path)).unwrap_or_else(|err| exit_with_config_error(&err))
+    } else {
+        Config::discover(start)
+            .unwrap_or_else(|err| exit_with_config_error(&err))
+            .map(|(_, config)| config)
+            .unwrap_or_default()
+    }
+}
+
+fn build_scanner(matches: &ArgMatches, config: &Config) -> Scanner {
+    let mut scanner =
+        Scanner::with_config(config).unwrap_or_else(|err| exit_with_config_error(&err));
+    for path in matches.values_of("languages").into_iter().flatten() {
+        if let Err(err) = scanner.load_languages(Path::new(path)) {
+            exit_with_config_error(&err);
         }
     }
+    scanner
+}
+
+fn print_languages(scanner: &Scanner) {
+    let stdout = io::stdout();
+    let mut stdout_handle = stdout.lock();
+    for (lang, extensions, filenames) in scanner.languages().list() {
+        let names: Vec<_> = extensions.into_iter().chain(filenames).collect();
+        print_safe(
+            &mut stdout_handle,
+            &format!("{:<16} {}\n", lang.to_string(), names.join(", ")),
+        );
+    }
 }
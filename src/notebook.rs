@@ -0,0 +1,121 @@
+use counter::{Counter, MixedLines, Sloc};
+use lang::Languages;
+use scanner::ErrorKind;
+use serde_json::{self, Value};
+use std::path::Path;
+
+pub const EXTENSION: &str = "ipynb";
+
+// Counts a Jupyter notebook as the language of its kernel. Code cells are
+// counted as that language and Markdown cells as comments.
+pub fn count(
+    path: &Path,
+    bytes: &[u8],
+    languages: &Languages,
+    mixed_lines: MixedLines,
+) -> Result<Sloc, ErrorKind> {
+    let not_notebook = || ErrorKind::Other("not a notebook".to_owned());
+    let notebook: Value = serde_json::from_slice(bytes).map_err(|_| not_notebook())?;
+    let cells = notebook["cells"].as_array().ok_or_else(not_notebook)?;
+
+    let metadata = &notebook["metadata"];
+    let lang = metadata["kernelspec"]["language"]
+        .as_str()
+        .or_else(|| metadata["language_info"]["name"].as_str())
+        .and_then(|kernel| languages.find_ref(kernel))
+        .ok_or_else(|| ErrorKind::Other("unknown kernel language".to_owned()))?;
+    let comment_info = languages.comment_info(lang).unwrap();
+
+    let mut sloc = Sloc::new(lang.clone());
+    sloc.stats.files = 1;
+    for cell in cells {
+        let source = match cell["source"] {
+            Value::String(ref s) => s.clone(),
+            Value::Array(ref lines) => lines.iter().filter_map(|l| l.as_str()).collect(),
+            _ => continue,
+        };
+
+        match cell["cell_type"].as_str() {
+            Some("code") => {
+                let mut counter = Counter::new(path, lang.clone(), comment_info, mixed_lines);
+                let cell_sloc = counter.count_bytes(source.as_bytes());
+                sloc.stats.lines += cell_sloc.stats.lines;
+                sloc.stats.code += cell_sloc.stats.code;
+                sloc.stats.comments += cell_sloc.stats.comments;
+                sloc.stats.blanks += cell_sloc.stats.blanks;
+                sloc.stats.mixed += cell_sloc.stats.mixed;
+            }
+            Some("markdown") => {
+                for line in source.lines() {
+                    sloc.stats.lines += 1;
+                    if line.trim().is_empty() {
+                        sloc.stats.blanks += 1;
+                    } else {
+                        sloc.stats.comments += 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(sloc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::count;
+    use counter::MixedLines;
+    use lang::Languages;
+    use scanner::ErrorKind;
+    use std::path::Path;
+
+    fn count_notebook(json: &str) -> Result<(u64, u64, u64, u64), ErrorKind> {
+        let languages = Languages::bundled();
+        let sloc = count(
+            Path::new("a.ipynb"),
+            json.as_bytes(),
+            &languages,
+            MixedLines::Code,
+        )?;
+        assert_eq!(sloc.lang.name(), "Python");
+        let stats = sloc.stats;
+        Ok((stats.lines, stats.code, stats.comments, stats.blanks))
+    }
+
+    #[test]
+    fn counts_code_and_markdown_cells() {
+        let json = r##"{
+            "metadata": {"kernelspec": {"language": "python"}},
+            "cells": [
+                {"cell_type": "code", "source": ["import os\n", "\n", "# setup\n", "x = 1"]},
+                {"cell_type": "code", "source": "print(x)  # show\ny = 2\n"},
+                {"cell_type": "markdown", "source": ["# Title\n", "\n", "Some text"]},
+                {"cell_type": "raw", "source": "ignored"}
+            ]
+        }"##;
+        assert_eq!(count_notebook(json), Ok((9, 4, 3, 2)));
+    }
+
+    #[test]
+    fn falls_back_to_language_info() {
+        let json = r#"{
+            "metadata": {"language_info": {"name": "python"}},
+            "cells": [{"cell_type": "code", "source": "x = 1"}]
+        }"#;
+        assert_eq!(count_notebook(json), Ok((1, 1, 0, 0)));
+    }
+
+    #[test]
+    fn reports_broken_notebooks() {
+        let unknown = r#"{"metadata": {"kernelspec": {"language": "cobol-ish"}}, "cells": []}"#;
+        assert_eq!(
+            count_notebook(unknown),
+            Err(ErrorKind::Other("unknown kernel language".to_owned()))
+        );
+        assert_eq!(
+            count_notebook("{ not json"),
+            Err(ErrorKind::Other("not a notebook".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,610 @@
+use countcode::{DirSloc, DirTree, FileSloc, Stats, Summary};
+use serde_json;
+use std::io::{self, StdoutLock, Write};
+
+const TOTAL: &str = "TOTAL";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+    Tsv,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Column {
+    Files,
+    Lines,
+    Code,
+    Comments,
+    Blanks,
+    Mixed,
+    CommentRatio,
+    CodeShare,
+}
+
+impl Column {
+    pub fn from_name(name: &str) -> Option<Column> {
+        match name {
+            "files" => Some(Column::Files),
+            "lines" => Some(Column::Lines),
+            "code" => Some(Column::Code),
+            "comments" => Some(Column::Comments),
+            "blanks" => Some(Column::Blanks),
+            "mixed" => Some(Column::Mixed),
+            "comment-ratio" => Some(Column::CommentRatio),
+            "code-share" => Some(Column::CodeShare),
+            _ => None,
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Column::Files => "Files",
+            Column::Lines => "Lines",
+            Column::Code => "Code",
+            Column::Comments => "Comments",
+            Column::Blanks => "Blanks",
+            Column::Mixed => "Mixed",
+            Column::CommentRatio => "Comment Ratio",
+            Column::CodeShare => "Code %",
+        }
+    }
+
+    fn cell(self, stats: &Stats, total: &Stats) -> String {
+        match self {
+            Column::Files => stats.files.to_string(),
+            Column::Lines => stats.lines.to_string(),
+            Column::Code => stats.code.to_string(),
+            Column::Comments => stats.comments.to_string(),
+            Column::Blanks => stats.blanks.to_string(),
+            Column::Mixed => stats.mixed.to_string(),
+            // Comment lines per line of code.
+            Column::CommentRatio => format!("{:.2}", ratio(stats.comments, stats.code)),
+            // Share of the total lines of code, as a percentage.
+            Column::CodeShare => format!("{:.1}", 100.0 * ratio(stats.code, total.code)),
+        }
+    }
+}
+
+fn ratio(a: u64, b: u64) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub format: Format,
+    pub columns: Option<Vec<Column>>,
+    pub show_mixed: bool,
+    pub show_total: bool,
+}
+
+impl Options {
+    // The columns to show after the name columns. Unless chosen explicitly,
+    // these are all the counts, leaving out `Files` in the per-file listing.
+    fn columns(&self, with_files: bool) -> Vec<Column> {
+        if let Some(ref columns) = self.columns {
+            return columns.clone();
+        }
+
+        let mut columns = Vec::new();
+        if with_files {
+            columns.push(Column::Files);
+        }
+        columns.extend(&[
+            Column::Lines,
+            Column::Code,
+            Column::Comments,
+            Column::Blanks,
+        ]);
+        if self.show_mixed {
+            columns.push(Column::Mixed);
+        }
+        columns
+    }
+}
+
+// A report laid out as rows of cells. The first `text_columns` columns hold
+// text and the rest hold numbers.
+struct Table {
+    headers: Vec<&'static str>,
+    text_columns: usize,
+    rows: Vec<Vec<String>>,
+    total: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct JsonLanguage<'a> {
+    name: String,
+    #[serde(flatten)]
+    stats: &'a Stats,
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    languages: Vec<JsonLanguage<'a>>,
+    total: &'a Stats,
+}
+
+#[derive(Serialize)]
+struct JsonFile<'a> {
+    path: String,
+    language: String,
+    #[serde(flatten)]
+    stats: &'a Stats,
+}
+
+#[derive(Serialize)]
+struct JsonFileReport<'a> {
+    files: Vec<JsonFile<'a>>,
+    total: &'a Stats,
+}
+
+#[derive(Serialize)]
+struct JsonDirectory<'a> {
+    path: String,
+    #[serde(flatten)]
+    stats: &'a Stats,
+}
+
+#[derive(Serialize)]
+struct JsonDirectoryReport<'a> {
+    directories: Vec<JsonDirectory<'a>>,
+    total: &'a Stats,
+}
+
+#[derive(Serialize)]
+struct JsonTree<'a> {
+    path: String,
+    #[serde(flatten)]
+    stats: &'a Stats,
+    children: Vec<JsonTree<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonTreeReport<'a> {
+    tree: Vec<JsonTree<'a>>,
+    total: &'a Stats,
+}
+
+pub fn print_languages(summary: &Summary, options: &Options) {
+    if options.format == Format::Json {
+        let report = JsonReport {
+            languages: summary
+                .languages
+                .iter()
+                .map(|s| JsonLanguage {
+                    name: s.lang.to_string(),
+                    stats: &s.stats,
+                })
+                .collect(),
+            total: &summary.total,
+        };
+        print_json(&report);
+        return;
+    }
+
+    let columns = options.columns(true);
+    let mut headers = vec!["Language"];
+    headers.extend(stats_headers(&columns));
+    let mut total = vec![TOTAL.to_owned()];
+    total.extend(stats_cells(&summary.total, &summary.total, &columns));
+
+    let table = Table {
+        headers,
+        text_columns: 1,
+        rows: summary
+            .languages
+            .iter()
+            .map(|s| {
+                let mut row = vec![s.lang.to_string()];
+                row.extend(stats_cells(&s.stats, &summary.total, &columns));
+                row
+            })
+            .collect(),
+        total,
+    };
+    print_table(&table, options);
+}
+
+pub fn print_files(files: &[&FileSloc], total: &Stats, options: &Options) {
+    if options.format == Format::Json {
+        let report = JsonFileReport {
+            files: files
+                .iter()
+                .map(|f| JsonFile {
+                    path: f.path.display().to_string(),
+                    language: f.sloc.lang.to_string(),
+                    stats: &f.sloc.stats,
+                })
+                .collect(),
+            total,
+        };
+        print_json(&report);
+        return;
+    }
+
+    let columns = options.columns(false);
+    let mut headers = vec!["File", "Language"];
+    headers.extend(stats_headers(&columns));
+    let mut total_row = vec![TOTAL.to_owned(), String::new()];
+    total_row.extend(stats_cells(total, total, &columns));
+
+    let table = Table {
+        headers,
+        text_columns: 2,
+        rows: files
+            .iter()
+            .map(|f| {
+                let mut row = vec![f.path.display().to_string(), f.sloc.lang.to_string()];
+                row.extend(stats_cells(&f.sloc.stats, total, &columns));
+                row
+            })
+            .collect(),
+        total: total_row,
+    };
+    print_table(&table, options);
+}
+
+pub fn print_directories(dirs: &[DirSloc], total: &Stats, options: &Options) {
+    if options.format == Format::Json {
+        let report = JsonDirectoryReport {
+            directories: dirs
+                .iter()
+                .map(|d| JsonDirectory {
+                    path: d.path.display().to_string(),
+                    stats: &d.stats,
+                })
+                .collect(),
+            total,
+        };
+        print_json(&report);
+        return;
+    }
+
+    let columns = options.columns(true);
+    let mut headers = vec!["Directory"];
+    headers.extend(stats_headers(&columns));
+    let mut total_row = vec![TOTAL.to_owned()];
+    total_row.extend(stats_cells(total, total, &columns));
+
+    let table = Table {
+        headers,
+        text_columns: 1,
+        rows: dirs
+            .iter()
+            .map(|d| {
+                let mut row = vec![d.path.display().to_string()];
+                row.extend(stats_cells(&d.stats, total, &columns));
+                row
+            })
+            .collect(),
+        total: total_row,
+    };
+    print_table(&table, options);
+}
+
+pub fn print_tree(trees: &[DirTree], total: &Stats, options: &Options) {
+    if options.format == Format::Json {
+        fn to_json<'a>(tree: &'a DirTree) -> JsonTree<'a> {
+            JsonTree {
+                path: tree.path.display().to_string(),
+                stats: &tree.stats,
+                children: tree.children.iter().map(to_json).collect(),
+            }
+        }
+
+        let report = JsonTreeReport {
+            tree: trees.iter().map(to_json).collect(),
+            total,
+        };
+        print_json(&report);
+        return;
+    }
+
+    // Tree branches only make sense in the table; CSV and TSV rows carry the
+    // full path of each directory instead.
+    struct Context<'a> {
+        total: &'a Stats,
+        columns: Vec<Column>,
+        format: Format,
+    }
+
+    fn add_rows(
+        tree: &DirTree,
+        prefix: &str,
+        name: String,
+        rows: &mut Vec<Vec<String>>,
+        context: &Context,
+    ) {
+        let mut row = vec![name];
+        row.extend(stats_cells(&tree.stats, context.total, &context.columns));
+        rows.push(row);
+
+        let len = tree.children.len();
+        for (i, child) in tree.children.iter().enumerate() {
+            let last = i + 1 == len;
+            let name = if context.format == Format::Table {
+                let file_name = child.path.file_name().unwrap_or_default().to_string_lossy();
+                format!(
+                    "{}{}{}",
+                    prefix,
+                    if last { "└── " } else { "├── " },
+                    file_name
+                )
+            } else {
+                child.path.display().to_string()
+            };
+            let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            add_rows(child, &child_prefix, name, rows, context);
+        }
+    }
+
+    let context = Context {
+        total,
+        columns: options.columns(true),
+        format: options.format,
+    };
+    let mut rows = Vec::new();
+    for tree in trees {
+        add_rows(
+            tree,
+            "",
+            tree.path.display().to_string(),
+            &mut rows,
+            &context,
+        );
+    }
+
+    let mut headers = vec!["Directory"];
+    headers.extend(stats_headers(&context.columns));
+    let mut total_row = vec![TOTAL.to_owned()];
+    total_row.extend(stats_cells(total, total, &context.columns));
+
+    let table = Table {
+        headers,
+        text_columns: 1,
+        rows,
+        total: total_row,
+    };
+    print_table(&table, options);
+}
+
+fn stats_headers(columns: &[Column]) -> Vec<&'static str> {
+    columns.iter().map(|c| c.header()).collect()
+}
+
+fn stats_cells(stats: &Stats, total: &Stats, columns: &[Column]) -> Vec<String> {
+    columns.iter().map(|c| c.cell(stats, total)).collect()
+}
+
+fn print_table(table: &Table, options: &Options) {
+    match options.format {
+        Format::Csv => print_delimited(table, ",", options.show_total, quote_csv),
+        Format::Tsv => print_delimited(table, "\t", options.show_total, escape_tsv),
+        _ => pretty_print(table),
+    }
+}
+
+fn print_json<T: ::serde::Serialize>(report: &T) {
+    let stdout = io::stdout();
+    let mut stdout_handle = stdout.lock();
+    let json = serde_json::to_string_pretty(report).unwrap();
+    print_safe(&mut stdout_handle, &json);
+    print_safe(&mut stdout_handle, "\n");
+}
+
+fn print_delimited(table: &Table, separator: &str, show_total: bool, escape: fn(&str) -> String) {
+    let stdout = io::stdout();
+    let mut stdout_handle = stdout.lock();
+    print_safe(
+        &mut stdout_handle,
+        &format_delimited(table, separator, show_total, escape),
+    );
+}
+
+fn format_delimited(
+    table: &Table,
+    separator: &str,
+    show_total: bool,
+    escape: fn(&str) -> String,
+) -> String {
+    let mut out = table.headers.join(separator);
+    out.push('\n');
+
+    let total = if show_total { Some(&table.total) } else { None };
+    for row in table.rows.iter().chain(total) {
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                if i < table.text_columns {
+                    escape(cell)
+                } else {
+                    cell.clone()
+                }
+            })
+            .collect();
+        out.push_str(&cells.join(separator));
+        out.push('\n');
+    }
+    out
+}
+
+// Text fields are always quoted so that spreadsheets don't try to interpret
+// names such as `C#` or `Objective C`.
+fn quote_csv(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+// TSV has no quoting, so tabs and line breaks can't appear in a field.
+fn escape_tsv(s: &str) -> String {
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+fn pretty_print(table: &Table) {
+    let mut widths: Vec<usize> = table.headers.iter().map(|h| h.len().max(10)).collect();
+    for row in table.rows.iter().chain(Some(&table.total)) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            let len = cell.chars().count();
+            if *width < len {
+                *width = len;
+            }
+        }
+    }
+
+    for width in widths.iter_mut() {
+        *width += 1;
+    }
+
+    let stdout = io::stdout();
+    let mut stdout_handle = stdout.lock();
+
+    let total_len = widths.iter().sum::<usize>() + widths.len() + 1;
+    print_separator(&mut stdout_handle, "=", total_len);
+    print_row(
+        &mut stdout_handle,
+        &table.headers,
+        &widths,
+        table.text_columns,
+    );
+    print_separator(&mut stdout_handle, "-", total_len);
+
+    for row in &table.rows {
+        print_row(&mut stdout_handle, row, &widths, table.text_columns);
+    }
+
+    print_separator(&mut stdout_handle, "=", total_len);
+    print_row(
+        &mut stdout_handle,
+        &table.total,
+        &widths,
+        table.text_columns,
+    );
+    print_separator(&mut stdout_handle, "=", total_len);
+}
+
+fn print_row<S: AsRef<str>>(
+    handle: &mut StdoutLock,
+    row: &[S],
+    widths: &[usize],
+    text_columns: usize,
+) {
+    let mut line = String::new();
+    for (i, (cell, width)) in row.iter().zip(widths).enumerate() {
+        if i < text_columns {
+            line.push_str(&format!(" {:<w$}", cell.as_ref(), w = width));
+        } else {
+            line.push_str(&format!(" {:>w$}", cell.as_ref(), w = width));
+        }
+    }
+    line.push_str(" \n");
+    print_safe(handle, &line);
+}
+
+fn print_separator(handle: &mut StdoutLock, c: &str, len: usize) {
+    for _ in 0..len {
+        print_safe(handle, c);
+    }
+    print_safe(handle, "\n");
+}
+
+pub fn print_safe(handle: &mut StdoutLock, s: &str) {
+    match handle.write_all(s.as_bytes()) {
+        Ok(_) => {}
+        Err(_) => {
+            std::process::exit(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_tsv, format_delimited, quote_csv, JsonLanguage, JsonReport, Table, TOTAL};
+    use countcode::Stats;
+    use serde_json::{self, Value};
+
+    fn table(names: &[&str]) -> Table {
+        Table {
+            headers: vec!["Language", "Code"],
+            text_columns: 1,
+            rows: names
+                .iter()
+                .map(|name| vec![name.to_string(), "1".to_owned()])
+                .collect(),
+            total: vec![TOTAL.to_owned(), names.len().to_string()],
+        }
+    }
+
+    #[test]
+    fn quotes_csv_text_fields() {
+        let table = table(&["C#", "say \"hi\""]);
+        assert_eq!(
+            format_delimited(&table, ",", true, quote_csv),
+            "Language,Code\n\"C#\",1\n\"say \"\"hi\"\"\",1\n\"TOTAL\",2\n"
+        );
+    }
+
+    #[test]
+    fn replaces_tabs_in_tsv_fields() {
+        let table = table(&["src/a\tb.rs"]);
+        assert_eq!(
+            format_delimited(&table, "\t", true, escape_tsv),
+            "Language\tCode\nsrc/a b.rs\t1\nTOTAL\t1\n"
+        );
+    }
+
+    #[test]
+    fn leaves_out_total_row() {
+        let table = table(&["Rust"]);
+        assert_eq!(
+            format_delimited(&table, ",", false, quote_csv),
+            "Language,Code\n\"Rust\",1\n"
+        );
+    }
+
+    #[test]
+    fn keeps_json_schema() {
+        let stats = Stats {
+            files: 12,
+            lines: 3400,
+            code: 2900,
+            comments: 250,
+            blanks: 250,
+            mixed: 0,
+        };
+        let report = JsonReport {
+            languages: vec![JsonLanguage {
+                name: "Rust".to_owned(),
+                stats: &stats,
+            }],
+            total: &stats,
+        };
+        let actual = serde_json::to_value(&report).unwrap();
+
+        // The example from the README.
+        let expected: Value = serde_json::from_str(
+            r#"{
+                "languages": [{
+                    "name": "Rust",
+                    "files": 12, "lines": 3400, "code": 2900,
+                    "comments": 250, "blanks": 250, "mixed": 0
+                }],
+                "total": {
+                    "files": 12, "lines": 3400, "code": 2900,
+                    "comments": 250, "blanks": 250, "mixed": 0
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(actual, expected);
+        for (_, count) in actual["total"].as_object().unwrap() {
+            assert!(count.is_u64());
+        }
+    }
+}
@@ -1,101 +1,966 @@
-use counter::{Counter, Sloc, SlocStr};
-use ignore::Walk;
-use lang::{CommentInfo, Lang};
+use config::{self, Config};
+use counter::{Counter, MixedLines, Sloc, Stats};
+use detect;
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{self, WalkBuilder};
+use lang::{self, Lang, LanguageDef, Languages};
+use notebook;
 use rayon::prelude::*;
+use std::cmp::Ordering;
 use std::collections::hash_map::{Entry, HashMap};
-use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Like .gitignore, but only read by countcode.
+pub const IGNORE_FILENAME: &str = ".countcodeignore";
+
+// How much of a file is read to guess its language from its content.
+const HEAD_LEN: usize = 16 * 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    Io(io::ErrorKind),
+    Loop,
+    NotRegularFile,
+    Other(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct FileError {
+    pub path: PathBuf,
+    pub kind: ErrorKind,
+}
+
+impl FileError {
+    fn from_walk_error(root: &str, err: ignore::Error) -> Self {
+        let mut path = PathBuf::from(root);
+        let mut err = err;
+        loop {
+            match err {
+                ignore::Error::WithPath { path: p, err: e } => {
+                    path = p;
+                    err = *e;
+                }
+                ignore::Error::WithDepth { err: e, .. }
+                | ignore::Error::WithLineNumber { err: e, .. } => {
+                    err = *e;
+                }
+                ignore::Error::Loop { child, .. } => {
+                    return FileError {
+                        path: child,
+                        kind: ErrorKind::Loop,
+                    };
+                }
+                ignore::Error::Io(e) => {
+                    return FileError {
+                        path,
+                        kind: ErrorKind::Io(e.kind()),
+                    };
+                }
+                e => {
+                    return FileError {
+                        path,
+                        kind: ErrorKind::Other(e.to_string()),
+                    };
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Io(kind) => write!(f, "{}", io::Error::from(kind)),
+            ErrorKind::Loop => write!(f, "symbolic link loop"),
+            ErrorKind::NotRegularFile => write!(f, "not a regular file"),
+            ErrorKind::Other(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.kind)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Name,
+    Files,
+    Lines,
+    Code,
+    Comments,
+    Blanks,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum SortOrder {
+    #[serde(rename = "asc")]
+    Ascending,
+    #[serde(rename = "desc")]
+    Descending,
+}
+
+impl SortKey {
+    // Names read best in alphabetical order, counts with the largest first.
+    pub fn default_order(self) -> SortOrder {
+        match self {
+            SortKey::Name => SortOrder::Ascending,
+            _ => SortOrder::Descending,
+        }
+    }
+
+    fn stat(self, stats: &Stats) -> u64 {
+        match self {
+            SortKey::Name => 0,
+            SortKey::Files => stats.files,
+            SortKey::Lines => stats.lines,
+            SortKey::Code => stats.code,
+            SortKey::Comments => stats.comments,
+            SortKey::Blanks => stats.blanks,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FileSloc {
+    pub path: PathBuf,
+    pub sloc: Sloc,
+}
+
+#[derive(Clone, Debug)]
+pub struct Summary {
+    pub roots: Vec<PathBuf>,
+    pub languages: Vec<Sloc>,
+    pub files: Vec<FileSloc>,
+    pub total: Stats,
+}
 
 pub struct Scanner {
-    extensions: HashMap<&'static str, Lang>,
-    comment_info: HashMap<Lang, CommentInfo>,
+    languages: Languages,
+    // Globs containing a `/` are matched against the whole relative path,
+    // others against the file name alone.
+    language_overrides: Vec<(GlobMatcher, bool, Lang)>,
+    mixed_lines: MixedLines,
+    sort_key: SortKey,
+    sort_order: SortOrder,
+    includes: Vec<String>,
+    excludes: Vec<String>,
+    config_root: Option<PathBuf>,
+    config_overrides: Option<Override>,
+    config_excludes: Vec<String>,
+    hidden: bool,
+    follow_links: bool,
+    no_ignore: bool,
+    no_ignore_vcs: bool,
+    max_depth: Option<usize>,
+    non_utf8_files: Vec<PathBuf>,
+    errors: Vec<FileError>,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Scanner {
     pub fn new() -> Self {
-        let extensions = Lang::extensions();
-        let comment_info = Lang::comment_info();
         Self {
-            extensions,
-            comment_info,
+            languages: Languages::bundled(),
+            language_overrides: Vec::new(),
+            mixed_lines: MixedLines::Code,
+            sort_key: SortKey::Lines,
+            sort_order: SortOrder::Descending,
+            includes: Vec::new(),
+            excludes: Vec::new(),
+            config_root: None,
+            config_overrides: None,
+            config_excludes: Vec::new(),
+            hidden: false,
+            follow_links: false,
+            no_ignore: false,
+            no_ignore_vcs: false,
+            max_depth: None,
+            non_utf8_files: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn with_config(config: &Config) -> Result<Self, config::Error> {
+        let mut scanner = Self::new();
+
+        for path in &config.language_files {
+            scanner.load_languages(path)?;
+        }
+        for def in &config.language {
+            scanner.add_language(def)?;
+        }
+        for (glob, name) in &config.languages {
+            let lang = scanner
+                .find_language(name)
+                .ok_or_else(|| config::Error::UnknownLanguage(name.clone()))?;
+            scanner.add_language_override(glob, lang)?;
+        }
+
+        // Globs in a config file are relative to the directory holding it,
+        // whichever paths are scanned. A config that wasn't loaded from a
+        // file has no such directory, so its globs act like the ones given
+        // on the command line.
+        match config.root {
+            Some(ref root) => {
+                let mut overrides = OverrideBuilder::new(root);
+                for glob in &config.include {
+                    overrides
+                        .add(glob)
+                        .map_err(|e| config::Error::InvalidGlob(glob.clone(), e.to_string()))?;
+                }
+                for glob in &config.exclude {
+                    overrides
+                        .add(&format!("!{}", glob))
+                        .map_err(|e| config::Error::InvalidGlob(glob.clone(), e.to_string()))?;
+                }
+                let overrides = overrides
+                    .build()
+                    .map_err(|e| config::Error::InvalidGlob(String::new(), e.to_string()))?;
+                scanner.config_root = Some(root.clone());
+                scanner.config_excludes = config.exclude.clone();
+                if !overrides.is_empty() {
+                    scanner.config_overrides = Some(overrides);
+                }
+            }
+            None => {
+                for glob in &config.include {
+                    scanner
+                        .add_include(glob)
+                        .map_err(|e| config::Error::InvalidGlob(glob.clone(), e.to_string()))?;
+                }
+                for glob in &config.exclude {
+                    scanner
+                        .add_exclude(glob)
+                        .map_err(|e| config::Error::InvalidGlob(glob.clone(), e.to_string()))?;
+                }
+            }
+        }
+
+        if let Some(hidden) = config.hidden {
+            scanner.set_hidden(hidden);
+        }
+        if let Some(follow) = config.follow {
+            scanner.set_follow_links(follow);
+        }
+        if config.max_depth.is_some() {
+            scanner.set_max_depth(config.max_depth);
+        }
+        if let Some(mixed) = config.mixed {
+            scanner.set_mixed_lines(mixed);
+        }
+        if let Some(sort) = config.sort {
+            scanner.set_sort_key(sort);
+            scanner.set_sort_order(sort.default_order());
         }
+        if let Some(order) = config.order {
+            scanner.set_sort_order(order);
+        }
+
+        Ok(scanner)
+    }
+
+    pub fn languages(&self) -> &Languages {
+        &self.languages
+    }
+
+    pub fn find_language(&self, name: &str) -> Option<Lang> {
+        self.languages.find(name)
     }
 
-    pub fn scan(&mut self, args: HashSet<String>) -> Vec<SlocStr> {
+    pub fn add_language(&mut self, def: &LanguageDef) -> Result<Lang, globset::Error> {
+        self.languages.add(def)
+    }
+
+    // Adds every definition in a file of the same form as the bundled one.
+    pub fn load_languages(&mut self, path: &Path) -> Result<(), config::Error> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| config::Error::Io(path.to_path_buf(), e))?;
+        let defs = lang::parse_definitions(&contents)
+            .map_err(|e| config::Error::Parse(path.to_path_buf(), e))?;
+        for def in &defs {
+            self.languages.add(def)?;
+        }
+        Ok(())
+    }
+
+    // Files whose path matches `glob` are counted as `lang` whatever their
+    // extension. A glob with a `/` in it is matched against the path relative
+    // to the config file's directory, or else to the scanned path; any other
+    // glob against the file name. Overrides are tried in the order they were
+    // added.
+    pub fn add_language_override(&mut self, glob: &str, lang: Lang) -> Result<(), globset::Error> {
+        let whole_path = glob.contains('/');
+        let matcher = GlobBuilder::new(glob.trim_start_matches('/'))
+            .literal_separator(whole_path)
+            .build()?
+            .compile_matcher();
+        self.language_overrides.push((matcher, whole_path, lang));
+        Ok(())
+    }
+
+    pub fn set_mixed_lines(&mut self, mixed_lines: MixedLines) {
+        self.mixed_lines = mixed_lines;
+    }
+
+    pub fn set_sort_key(&mut self, sort_key: SortKey) {
+        self.sort_key = sort_key;
+    }
+
+    pub fn set_sort_order(&mut self, sort_order: SortOrder) {
+        self.sort_order = sort_order;
+    }
+
+    // Once any include glob is added, only files matching one are counted.
+    pub fn add_include(&mut self, glob: &str) -> Result<(), ignore::Error> {
+        OverrideBuilder::new("").add(glob)?;
+        self.includes.push(glob.to_owned());
+        Ok(())
+    }
+
+    pub fn add_exclude(&mut self, glob: &str) -> Result<(), ignore::Error> {
+        OverrideBuilder::new("").add(&format!("!{}", glob))?;
+        self.excludes.push(glob.to_owned());
+        Ok(())
+    }
+
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
+    pub fn set_follow_links(&mut self, follow_links: bool) {
+        self.follow_links = follow_links;
+    }
+
+    // Skips all ignore files: .gitignore, .ignore and .countcodeignore.
+    pub fn set_no_ignore(&mut self, no_ignore: bool) {
+        self.no_ignore = no_ignore;
+    }
+
+    // Skips only the ignore files that come from git.
+    pub fn set_no_ignore_vcs(&mut self, no_ignore_vcs: bool) {
+        self.no_ignore_vcs = no_ignore_vcs;
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: Option<usize>) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn non_utf8_files(&self) -> &[PathBuf] {
+        &self.non_utf8_files
+    }
+
+    pub fn errors(&self) -> &[FileError] {
+        &self.errors
+    }
+
+    pub fn scan(&mut self, args: &[String]) -> Summary {
+        self.errors.clear();
+
         let mut paths = Vec::new();
         for a in args.iter() {
-            for entry in Walk::new(a).filter_map(|e| e.ok()) {
-                paths.push(entry);
+            let arg = Path::new(a);
+            let abs_arg = arg.canonicalize().ok();
+
+            // Excludes are left to the walker, so that it skips excluded
+            // directories. Includes are checked for each file below instead,
+            // as the walker would let them win over ignore files.
+            let mut overrides = OverrideBuilder::new(a);
+            for glob in &self.excludes {
+                overrides
+                    .add(&format!("!{}", glob))
+                    .expect("exclude globs are validated when added");
+            }
+            // Config globs are checked for each file below, but excluded
+            // directories are skipped outright when scanning from the config
+            // file's directory.
+            if abs_arg.is_some() && abs_arg == self.config_root {
+                for glob in &self.config_excludes {
+                    overrides
+                        .add(&format!("!{}", glob))
+                        .expect("config globs are validated when loaded");
+                }
+            }
+            let mut includes = OverrideBuilder::new(a);
+            for glob in &self.includes {
+                includes
+                    .add(glob)
+                    .expect("include globs are validated when added");
+            }
+            let (overrides, includes) = match (overrides.build(), includes.build()) {
+                (Ok(overrides), Ok(includes)) => (overrides, includes),
+                (Err(err), _) | (_, Err(err)) => {
+                    self.errors.push(FileError::from_walk_error(a, err));
+                    continue;
+                }
+            };
+
+            let use_vcs_ignores = !self.no_ignore && !self.no_ignore_vcs;
+            let mut walk = WalkBuilder::new(a);
+            walk.hidden(!self.hidden)
+                .follow_links(self.follow_links)
+                .max_depth(self.max_depth)
+                .parents(!self.no_ignore)
+                .ignore(!self.no_ignore)
+                .git_ignore(use_vcs_ignores)
+                .git_global(use_vcs_ignores)
+                .git_exclude(use_vcs_ignores)
+                .overrides(overrides);
+            if !self.no_ignore {
+                walk.add_custom_ignore_filename(IGNORE_FILENAME);
+            }
+            let walk = walk.build();
+            for entry in walk {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        self.errors.push(FileError::from_walk_error(a, err));
+                        continue;
+                    }
+                };
+
+                let path = entry.path();
+                let in_config_root = match (&abs_arg, &self.config_root) {
+                    (Some(abs_arg), Some(root)) => relative_to(path, arg, abs_arg, root),
+                    _ => None,
+                };
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                // Like the walker, files passed directly are always counted.
+                if !is_dir && entry.depth() > 0 && includes.matched(path, false).is_ignore() {
+                    continue;
+                }
+                if let (Some(rel), Some(overrides)) = (&in_config_root, &self.config_overrides) {
+                    if !is_dir && is_overridden(overrides, rel) {
+                        continue;
+                    }
+                }
+
+                let rel = in_config_root
+                    .or_else(|| path.strip_prefix(arg).ok().map(Path::to_path_buf))
+                    .filter(|rel| !rel.as_os_str().is_empty())
+                    .unwrap_or_else(|| path.to_path_buf());
+                paths.push((entry, rel));
             }
         }
 
-        let extensions = &self.extensions;
-        let comment_info = &self.comment_info;
-        let count_result: Vec<Sloc> = paths
+        let languages = &self.languages;
+        let language_overrides = &self.language_overrides;
+        let mixed_lines = self.mixed_lines;
+        let count_result: Vec<Result<(FileSloc, bool), FileError>> = paths
             .par_iter()
-            .filter_map(|entry| {
+            .filter_map(|(entry, rel)| {
+                if entry.file_type().is_some_and(|t| t.is_dir()) {
+                    return None;
+                }
+
                 let path = entry.path();
-                let lang = {
-                    let lang = path
-                        .extension()
-                        .and_then(|e| e.to_str())
-                        .and_then(|e| extensions.get(e));
-
-                    if let Some(lang) = lang {
-                        Some(lang)
-                    } else {
-                        let is_mkfile = path
-                            .file_name()
+                // Only regular files are read to guess their language, as
+                // reading FIFOs or devices could block. Symlinks are followed
+                // to see what they point to.
+                let is_file = match entry.file_type() {
+                    Some(t) if t.is_symlink() => path.metadata().is_ok_and(|m| m.is_file()),
+                    t => t.is_some_and(|t| t.is_file()),
+                };
+                let lang = language_overrides
+                    .iter()
+                    .find(|(matcher, whole_path, _)| {
+                        if *whole_path {
+                            matcher.is_match(rel)
+                        } else {
+                            path.file_name().is_some_and(|n| matcher.is_match(n))
+                        }
+                    })
+                    .map(|(_, _, lang)| lang)
+                    .or_else(|| {
+                        path.file_name()
+                            .and_then(|n| n.to_str())
+                            .and_then(|n| languages.by_filename(n))
+                    })
+                    .or_else(|| {
+                        let lang = path
+                            .extension()
                             .and_then(|e| e.to_str())
-                            .map_or(false, |e| e.to_lowercase() == "makefile");
+                            .and_then(|e| languages.by_extension(e))?;
+                        if !detect::is_ambiguous(lang.name()) || !is_file {
+                            return Some(lang);
+                        }
+                        let guess = detect::read_head(path, HEAD_LEN)
+                            .ok()
+                            .and_then(|head| detect::disambiguate(lang.name(), &head))
+                            .and_then(|name| languages.find_ref(name));
+                        guess.or(Some(lang))
+                    })
+                    .or_else(|| {
+                        // Scripts often have no extension but name their
+                        // interpreter on the first line.
+                        if path.extension().is_some() || !is_file {
+                            return None;
+                        }
+                        let head = detect::read_head(path, HEAD_LEN).ok()?;
+                        detect::shebang_interpreter(&head)
+                            .and_then(|name| languages.by_interpreter(name))
+                    });
 
-                        if is_mkfile {
-                            Some(&Lang::Makefile)
-                        } else {
-                            None
+                let is_notebook = path.extension().is_some_and(|e| e == notebook::EXTENSION);
+                if lang.is_none() && is_notebook && is_file {
+                    return match fs::read(path) {
+                        Ok(bytes) => {
+                            let result = notebook::count(path, &bytes, languages, mixed_lines)
+                                .map(|sloc| {
+                                    let file = FileSloc {
+                                        path: path.to_path_buf(),
+                                        sloc,
+                                    };
+                                    (file, false)
+                                })
+                                .map_err(|kind| FileError {
+                                    path: path.to_path_buf(),
+                                    kind,
+                                });
+                            Some(result)
                         }
-                    }
-                };
+                        Err(e) => Some(Err(FileError {
+                            path: path.to_path_buf(),
+                            kind: ErrorKind::Io(e.kind()),
+                        })),
+                    };
+                }
 
                 match lang {
+                    // Reading FIFOs, sockets or devices could block forever.
+                    Some(_) if is_special_file(path) => Some(Err(FileError {
+                        path: path.to_path_buf(),
+                        kind: ErrorKind::NotRegularFile,
+                    })),
                     Some(lang) => {
-                        let comment_info = comment_info.get(lang).unwrap();
-                        let mut counter = Counter::new(&path, lang.clone(), comment_info.clone());
-                        counter.count()
+                        let comment_info = languages.comment_info(lang).unwrap();
+                        let mut counter =
+                            Counter::new(path, lang.clone(), comment_info, mixed_lines);
+                        let result = counter
+                            .count()
+                            .map(|sloc| {
+                                let file = FileSloc {
+                                    path: path.to_path_buf(),
+                                    sloc,
+                                };
+                                (file, counter.has_invalid_utf8())
+                            })
+                            .map_err(|e| FileError {
+                                path: path.to_path_buf(),
+                                kind: ErrorKind::Io(e.kind()),
+                            });
+                        Some(result)
                     }
                     None => None,
                 }
             })
             .collect();
 
+        self.non_utf8_files.clear();
+        let mut files = Vec::new();
         let mut sloc_map: HashMap<Lang, Sloc> = HashMap::new();
-        for sloc in count_result {
-            match sloc_map.entry(sloc.lang.clone()) {
+        for result in count_result {
+            let (file, invalid_utf8) = match result {
+                Ok(result) => result,
+                Err(err) => {
+                    self.errors.push(err);
+                    continue;
+                }
+            };
+
+            if invalid_utf8 {
+                self.non_utf8_files.push(file.path.clone());
+            }
+
+            match sloc_map.entry(file.sloc.lang.clone()) {
                 Entry::Occupied(ref mut e) => {
-                    e.get_mut().stats += sloc.stats;
+                    e.get_mut().stats += &file.sloc.stats;
                 }
                 Entry::Vacant(e) => {
-                    e.insert(sloc);
+                    e.insert(file.sloc.clone());
                 }
             }
+            files.push(file);
+        }
+
+        self.non_utf8_files.sort();
+        self.errors.sort_by(|a, b| a.path.cmp(&b.path));
+
+        // Sort by name first so that ties are listed in a stable order.
+        let mut sloc: Vec<_> = sloc_map.values().cloned().collect();
+        sloc.sort_by_key(|s| s.lang.to_string());
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let key = self.sort_key;
+        let order = |ordering: Ordering| match self.sort_order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        };
+        if key == SortKey::Name {
+            if self.sort_order == SortOrder::Descending {
+                sloc.reverse();
+                files.reverse();
+            }
+        } else {
+            sloc.sort_by(|a, b| order(key.stat(&a.stats).cmp(&key.stat(&b.stats))));
+            files.sort_by(|a, b| order(key.stat(&a.sloc.stats).cmp(&key.stat(&b.sloc.stats))));
         }
 
-        let mut sloc: Vec<_> = sloc_map.iter().map(|(_, v)| v.clone()).collect();
-        sloc.sort_by(|a, b| a.stats.lines.cmp(&b.stats.lines).reverse());
-        let mut total = Sloc::new(Lang::Total);
+        let mut total = Stats::default();
         for s in &sloc {
-            total.stats += &s.stats;
-        }
-        sloc.push(total);
-
-        sloc.iter()
-            .map(|s| SlocStr {
-                lang: format!("{}", s.lang),
-                files: format!("{}", s.stats.files),
-                lines: format!("{}", s.stats.lines),
-                code: format!("{}", s.stats.code),
-                comments: format!("{}", s.stats.comments),
-                blanks: format!("{}", s.stats.blanks),
+            total += &s.stats;
+        }
+
+        Summary {
+            roots: args.iter().map(PathBuf::from).collect(),
+            languages: sloc,
+            files,
+            total,
+        }
+    }
+}
+
+// Where a walked path sits relative to `root`, given the path the walk started
+// from and what that resolves to.
+fn relative_to(path: &Path, arg: &Path, abs_arg: &Path, root: &Path) -> Option<PathBuf> {
+    let rest = path.strip_prefix(arg).ok()?;
+    abs_arg
+        .join(rest)
+        .strip_prefix(root)
+        .ok()
+        .map(Path::to_path_buf)
+}
+
+// Whether config globs rule out a file, given relative to the config file's
+// directory. As when walking, files in an excluded directory are excluded too.
+fn is_overridden(overrides: &Override, rel: &Path) -> bool {
+    overrides.matched(rel, false).is_ignore()
+        || rel
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .any(|dir| overrides.matched(dir, true).is_ignore())
+}
+
+fn is_special_file(path: &Path) -> bool {
+    path.metadata().is_ok_and(|m| !m.is_file() && !m.is_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorKind, Scanner, SortKey, SortOrder, IGNORE_FILENAME};
+    use config::{self, Config, CONFIG_FILENAME};
+    use std::env;
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    // Lays out `files` under a fresh temporary directory.
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("countcode-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for &(path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn scan_files(scanner: &mut Scanner, path: &Path) -> Vec<(String, String)> {
+        let summary = scanner.scan(&[path.to_string_lossy().into_owned()]);
+        let mut files: Vec<_> = summary
+            .files
+            .iter()
+            .map(|f| {
+                let rel = f.path.strip_prefix(path).unwrap_or(&f.path);
+                (
+                    rel.to_string_lossy().into_owned(),
+                    f.sloc.lang.name().to_owned(),
+                )
             })
-            .collect()
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn config_globs_are_relative_to_config_file() {
+        let root = tree(
+            "config-globs",
+            &[
+                (
+                    CONFIG_FILENAME,
+                    "include = [\"src/**\"]\nexclude = [\"src/gen\"]\n",
+                ),
+                ("src/main.rs", "fn main() {}\n"),
+                ("src/gen/out.rs", "fn f() {}\n"),
+                ("build.rs", "fn main() {}\n"),
+            ],
+        );
+        let config = Config::load(&root.join(CONFIG_FILENAME)).unwrap();
+
+        let mut scanner = Scanner::with_config(&config).unwrap();
+        assert_eq!(
+            scan_files(&mut scanner, &root),
+            vec![("src/main.rs".to_owned(), "Rust".to_owned())]
+        );
+        assert_eq!(
+            scan_files(&mut scanner, &root.join("src")),
+            vec![("main.rs".to_owned(), "Rust".to_owned())]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn path_language_overrides_are_relative_to_config_file() {
+        let root = tree(
+            "config-overrides",
+            &[
+                (
+                    CONFIG_FILENAME,
+                    "[languages]\n\"src/gen/*.inc\" = \"C\"\n\"*.tpl\" = \"HTML\"\n",
+                ),
+                ("src/gen/a.inc", "int a;\n"),
+                ("src/b.inc", "int b;\n"),
+                ("src/page.tpl", "<p>\n"),
+            ],
+        );
+        let config = Config::load(&root.join(CONFIG_FILENAME)).unwrap();
+
+        let mut scanner = Scanner::with_config(&config).unwrap();
+        let expected = vec![
+            ("gen/a.inc".to_owned(), "C".to_owned()),
+            ("page.tpl".to_owned(), "HTML".to_owned()),
+        ];
+        assert_eq!(scan_files(&mut scanner, &root.join("src")), expected);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_the_targets_of_symlinks() {
+        let root = tree("symlinks", &[("bin/tool", "#!/bin/sh\necho hi\n")]);
+        ::std::os::unix::fs::symlink(root.join("bin/tool"), root.join("tool")).unwrap();
+
+        let mut scanner = Scanner::new();
+        let files = scan_files(&mut scanner, &root);
+        assert_eq!(
+            files,
+            vec![
+                ("bin/tool".to_owned(), "Shell".to_owned()),
+                ("tool".to_owned(), "Shell".to_owned()),
+            ]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn counts_empty_files() {
+        let root = tree("empty", &[("empty.rs", "")]);
+
+        let mut scanner = Scanner::new();
+        let summary = scanner.scan(&[root.to_string_lossy().into_owned()]);
+        assert_eq!(summary.files.len(), 1);
+        assert_eq!((summary.total.files, summary.total.lines), (1, 0));
+        assert!(scanner.errors().is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_fifos_instead_of_reading_them() {
+        let root = tree("fifo", &[]);
+        fs::create_dir_all(&root).unwrap();
+        let fifo = root.join("pipe.rs");
+        let status = process::Command::new("mkfifo").arg(&fifo).status().unwrap();
+        assert!(status.success());
+
+        let mut scanner = Scanner::new();
+        let summary = scanner.scan(&[root.to_string_lossy().into_owned()]);
+        assert!(summary.files.is_empty());
+        let errors: Vec<_> = scanner
+            .errors()
+            .iter()
+            .map(|e| (e.path.clone(), e.kind.clone()))
+            .collect();
+        assert_eq!(errors, vec![(fifo, ErrorKind::NotRegularFile)]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn collects_errors_for_broken_links_and_missing_paths() {
+        let root = tree("errors", &[("ok.rs", "fn f() {}\n")]);
+        let link = root.join("broken.rs");
+        ::std::os::unix::fs::symlink(root.join("gone.rs"), &link).unwrap();
+        let missing = root.join("missing");
+
+        let mut scanner = Scanner::new();
+        let args = vec![
+            root.to_string_lossy().into_owned(),
+            missing.to_string_lossy().into_owned(),
+        ];
+        let summary = scanner.scan(&args);
+        assert_eq!(summary.files.len(), 1);
+        let mut errors: Vec<_> = scanner
+            .errors()
+            .iter()
+            .map(|e| (e.path.clone(), e.kind.clone()))
+            .collect();
+        errors.sort_by(|a, b| a.0.cmp(&b.0));
+        let not_found = ErrorKind::Io(io::ErrorKind::NotFound);
+        assert_eq!(
+            errors,
+            vec![(link, not_found.clone()), (missing, not_found)]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn sorts_languages() {
+        let root = tree(
+            "sort",
+            &[
+                ("a.rs", "a\nb\nc\n"),
+                ("b.py", "a\nb\nc\n"),
+                ("c.go", "a\n"),
+                ("d.js", "a\nb\nc\nd\ne\n"),
+            ],
+        );
+        let sorted = |key: SortKey, order: SortOrder| {
+            let mut scanner = Scanner::new();
+            scanner.set_sort_key(key);
+            scanner.set_sort_order(order);
+            let summary = scanner.scan(&[root.to_string_lossy().into_owned()]);
+            summary
+                .languages
+                .iter()
+                .map(|s| s.lang.name().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(SortKey::Name.default_order(), SortOrder::Ascending);
+        assert_eq!(SortKey::Lines.default_order(), SortOrder::Descending);
+        assert_eq!(
+            sorted(SortKey::Name, SortOrder::Ascending),
+            ["Go", "JavaScript", "Python", "Rust"]
+        );
+        assert_eq!(
+            sorted(SortKey::Name, SortOrder::Descending),
+            ["Rust", "Python", "JavaScript", "Go"]
+        );
+        // Ties are listed by name, whichever the order.
+        assert_eq!(
+            sorted(SortKey::Lines, SortOrder::Descending),
+            ["JavaScript", "Python", "Rust", "Go"]
+        );
+        assert_eq!(
+            sorted(SortKey::Lines, SortOrder::Ascending),
+            ["Go", "Python", "Rust", "JavaScript"]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn filters_walked_files() {
+        let root = tree(
+            "filters",
+            &[
+                (IGNORE_FILENAME, "ignored.rs\n"),
+                ("ignored.rs", "fn f() {}\n"),
+                ("src/a.rs", "fn f() {}\n"),
+                ("src/b.rs", "fn f() {}\n"),
+                ("src/deep/x/c.rs", "fn f() {}\n"),
+                ("vendor/v.rs", "fn f() {}\n"),
+            ],
+        );
+        let paths = |configure: &dyn Fn(&mut Scanner)| {
+            let mut scanner = Scanner::new();
+            configure(&mut scanner);
+            scan_files(&mut scanner, &root)
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            paths(&|_| {}),
+            ["src/a.rs", "src/b.rs", "src/deep/x/c.rs", "vendor/v.rs"]
+        );
+        assert_eq!(
+            paths(&|s| {
+                s.add_include("*.rs").unwrap();
+                s.add_exclude("b.rs").unwrap();
+            }),
+            ["src/a.rs", "src/deep/x/c.rs", "vendor/v.rs"]
+        );
+        assert_eq!(
+            paths(&|s| s.add_include("src/**").unwrap()),
+            ["src/a.rs", "src/b.rs", "src/deep/x/c.rs"]
+        );
+        assert_eq!(
+            paths(&|s| s.add_exclude("vendor").unwrap()),
+            ["src/a.rs", "src/b.rs", "src/deep/x/c.rs"]
+        );
+        assert_eq!(
+            paths(&|s| s.set_no_ignore(true)),
+            [
+                "ignored.rs",
+                "src/a.rs",
+                "src/b.rs",
+                "src/deep/x/c.rs",
+                "vendor/v.rs"
+            ]
+        );
+        assert_eq!(
+            paths(&|s| s.set_max_depth(Some(2))),
+            ["src/a.rs", "src/b.rs", "vendor/v.rs"]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn reports_invalid_language_files() {
+        let root = tree(
+            "bad-languages",
+            &[("languages.toml", "[[language]]\nname = 1\n")],
+        );
+        let path = root.join("languages.toml");
+
+        let mut scanner = Scanner::new();
+        match scanner.load_languages(&path) {
+            Err(config::Error::Parse(p, _)) => assert_eq!(p, path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn missing_paths_have_no_config() {
+        let path = env::temp_dir().join("countcode-missing").join("a.rs");
+        assert!(Config::discover(&path).unwrap().is_none());
     }
 }